@@ -0,0 +1,62 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use sodium_rust::{SodiumCtx, Stream, StreamSink};
+
+// A left fold of `merge` costs about twice as much for each stream
+// added, so it is only measured for small numbers of streams. At
+// larger sizes `merge_all` is compared against `merge` chained as a
+// balanced tree instead.
+const FOLDED_SIZES: [usize; 3] = [4, 8, 12];
+const SIZES: [usize; 5] = [4, 8, 12, 100, 500];
+
+fn setup(n: usize) -> (SodiumCtx, Vec<StreamSink<i32>>, Vec<Stream<i32>>) {
+    let sodium_ctx = SodiumCtx::new();
+    let sinks: Vec<StreamSink<i32>> = (0..n).map(|_| sodium_ctx.new_stream_sink()).collect();
+    let streams: Vec<Stream<i32>> = sinks.iter().map(StreamSink::stream).collect();
+    (sodium_ctx, sinks, streams)
+}
+
+fn merge_tree(streams: &[Stream<i32>]) -> Stream<i32> {
+    if streams.len() == 1 {
+        return streams[0].clone();
+    }
+    let (left, right) = streams.split_at(streams.len() / 2);
+    merge_tree(left).merge(&merge_tree(right), |l: &i32, r: &i32| l + r)
+}
+
+fn merge(c: &mut Criterion) {
+    let mut group = c.benchmark_group("merge");
+    group.sample_size(10);
+    for n in FOLDED_SIZES.iter().cloned() {
+        let (_sodium_ctx, sinks, streams) = setup(n);
+        let folded = streams[1..].iter().fold(streams[0].clone(), |acc, s| {
+            acc.merge(s, |l: &i32, r: &i32| l + r)
+        });
+        let l = folded.listen(|_: &i32| {});
+        group.bench_with_input(BenchmarkId::new("folded", n), &n, |b, _| {
+            b.iter(|| sinks[n - 1].send(1))
+        });
+        l.unlisten();
+    }
+    for n in SIZES.iter().cloned() {
+        let (_sodium_ctx, sinks, streams) = setup(n);
+        let merged = merge_tree(&streams);
+        let l = merged.listen(|_: &i32| {});
+        group.bench_with_input(BenchmarkId::new("tree", n), &n, |b, _| {
+            b.iter(|| sinks[n - 1].send(1))
+        });
+        l.unlisten();
+    }
+    for n in SIZES.iter().cloned() {
+        let (sodium_ctx, sinks, streams) = setup(n);
+        let merged = Stream::merge_all(&sodium_ctx, &streams, |l: &i32, r: &i32| l + r);
+        let l = merged.listen(|_: &i32| {});
+        group.bench_with_input(BenchmarkId::new("merge_all", n), &n, |b, _| {
+            b.iter(|| sinks[n - 1].send(1))
+        });
+        l.unlisten();
+    }
+    group.finish();
+}
+
+criterion_group!(benches, merge);
+criterion_main!(benches);
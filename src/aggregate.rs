@@ -0,0 +1,272 @@
+use crate::impl_::lambda::IsLambda1;
+use crate::MapDiff;
+use crate::Monoid;
+
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::ops::Sub;
+
+// The running state of one group of an incremental aggregation over a
+// `CellMap`. Members are added and removed one at a time, by the key
+// they have in the source map.
+pub(crate) trait Aggregate<K, X> {
+    type Output;
+
+    fn insert(&mut self, k: &K, x: X);
+
+    fn remove(&mut self, k: &K, x: &X);
+
+    fn is_empty(&self) -> bool;
+
+    fn output(&mut self) -> Self::Output;
+}
+
+pub(crate) struct CountAgg {
+    count: usize,
+}
+
+impl CountAgg {
+    pub(crate) fn new() -> CountAgg {
+        CountAgg { count: 0 }
+    }
+}
+
+impl<K> Aggregate<K, ()> for CountAgg {
+    type Output = usize;
+
+    fn insert(&mut self, _k: &K, _x: ()) {
+        self.count += 1;
+    }
+
+    fn remove(&mut self, _k: &K, _x: &()) {
+        self.count -= 1;
+    }
+
+    fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    fn output(&mut self) -> usize {
+        self.count
+    }
+}
+
+pub(crate) struct SumAgg<S> {
+    sum: S,
+    count: usize,
+}
+
+impl<S: Monoid> SumAgg<S> {
+    pub(crate) fn new() -> SumAgg<S> {
+        SumAgg {
+            sum: S::empty(),
+            count: 0,
+        }
+    }
+}
+
+impl<K, S: Monoid + Sub<Output = S> + Clone> Aggregate<K, S> for SumAgg<S> {
+    type Output = S;
+
+    fn insert(&mut self, _k: &K, x: S) {
+        self.sum = self.sum.combine(&x);
+        self.count += 1;
+    }
+
+    fn remove(&mut self, _k: &K, x: &S) {
+        self.sum = self.sum.clone() - x.clone();
+        self.count -= 1;
+    }
+
+    fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    fn output(&mut self) -> S {
+        self.sum.clone()
+    }
+}
+
+pub(crate) struct GroupAgg<K, V> {
+    members: HashMap<K, V>,
+}
+
+impl<K: Eq + Hash, V> GroupAgg<K, V> {
+    pub(crate) fn new() -> GroupAgg<K, V> {
+        GroupAgg {
+            members: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> Aggregate<K, V> for GroupAgg<K, V> {
+    type Output = HashMap<K, V>;
+
+    fn insert(&mut self, k: &K, x: V) {
+        self.members.insert(k.clone(), x);
+    }
+
+    fn remove(&mut self, k: &K, _x: &V) {
+        self.members.remove(k);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    fn output(&mut self) -> HashMap<K, V> {
+        self.members.clone()
+    }
+}
+
+// Keeps the greatest value with a binary heap. Removed members are
+// only marked as dead, and are popped once they reach the top, so a
+// removal costs no more than an insertion.
+pub(crate) struct HeapAgg<K, X> {
+    heap: BinaryHeap<(X, u64)>,
+    live: HashMap<K, u64>,
+    live_ids: HashSet<u64>,
+    next_id: u64,
+}
+
+impl<K: Eq + Hash, X: Ord> HeapAgg<K, X> {
+    pub(crate) fn new() -> HeapAgg<K, X> {
+        HeapAgg {
+            heap: BinaryHeap::new(),
+            live: HashMap::new(),
+            live_ids: HashSet::new(),
+            next_id: 0,
+        }
+    }
+
+    fn pop_dead(&mut self) {
+        while let Some((_, id)) = self.heap.peek() {
+            if self.live_ids.contains(id) {
+                break;
+            }
+            self.heap.pop();
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash, X: Ord + Clone> Aggregate<K, X> for HeapAgg<K, X> {
+    type Output = X;
+
+    fn insert(&mut self, k: &K, x: X) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.heap.push((x, id));
+        self.live.insert(k.clone(), id);
+        self.live_ids.insert(id);
+    }
+
+    fn remove(&mut self, k: &K, _x: &X) {
+        if let Some(id) = self.live.remove(k) {
+            self.live_ids.remove(&id);
+        }
+        // Rebuild once dead entries dominate, to bound the memory.
+        if self.heap.len() > 2 * self.live_ids.len() + 16 {
+            let live_ids = &self.live_ids;
+            self.heap.retain(|(_, id)| live_ids.contains(id));
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.live.is_empty()
+    }
+
+    fn output(&mut self) -> X {
+        self.pop_dead();
+        self.heap.peek().unwrap().0.clone()
+    }
+}
+
+// The state of an aggregation of a `CellMap` by group: which group and
+// value each source key contributes, and the aggregate of each group.
+pub(crate) struct Grouping<K, V, G, X, AGG, GF, XF> {
+    group_fn: GF,
+    value_fn: XF,
+    new_agg: fn() -> AGG,
+    members: HashMap<K, (G, X)>,
+    groups: HashMap<G, AGG>,
+    _v: PhantomData<fn(&V)>,
+}
+
+impl<K, V, G, X, AGG, GF, XF> Grouping<K, V, G, X, AGG, GF, XF>
+where
+    K: Clone + Eq + Hash,
+    G: Clone + Eq + Hash,
+    X: Clone,
+    AGG: Aggregate<K, X>,
+    GF: IsLambda1<V, G>,
+    XF: IsLambda1<V, X>,
+{
+    pub(crate) fn new(group_fn: GF, value_fn: XF, new_agg: fn() -> AGG) -> Self {
+        Grouping {
+            group_fn,
+            value_fn,
+            new_agg,
+            members: HashMap::new(),
+            groups: HashMap::new(),
+            _v: PhantomData,
+        }
+    }
+
+    pub(crate) fn outputs(&mut self) -> HashMap<G, AGG::Output> {
+        self.groups
+            .iter_mut()
+            .map(|(g, agg)| (g.clone(), agg.output()))
+            .collect()
+    }
+
+    pub(crate) fn insert(&mut self, k: &K, v: &V) -> G {
+        let g = self.group_fn.call(v);
+        let x = self.value_fn.call(v);
+        let new_agg = self.new_agg;
+        self.groups
+            .entry(g.clone())
+            .or_insert_with(new_agg)
+            .insert(k, x.clone());
+        self.members.insert(k.clone(), (g.clone(), x));
+        g
+    }
+
+    pub(crate) fn remove(&mut self, k: &K) -> Option<G> {
+        let (g, x) = self.members.remove(k)?;
+        if let Some(agg) = self.groups.get_mut(&g) {
+            agg.remove(k, &x);
+        }
+        Some(g)
+    }
+
+    // Apply a batch of edits to the source map, and return the edits to
+    // the aggregated map, one for each group that was touched.
+    pub(crate) fn apply(&mut self, diffs: &[MapDiff<K, V>]) -> Vec<MapDiff<G, AGG::Output>> {
+        let mut touched = Vec::new();
+        let mut seen = HashSet::new();
+        let mut touch = |g: G| {
+            if seen.insert(g.clone()) {
+                touched.push(g);
+            }
+        };
+        for diff in diffs {
+            if let Some(g) = self.remove(diff.key()) {
+                touch(g);
+            }
+            if let MapDiff::Upsert(k, v) = diff {
+                touch(self.insert(k, v));
+            }
+        }
+        touched
+            .into_iter()
+            .map(|g| match self.groups.get_mut(&g) {
+                Some(agg) if !agg.is_empty() => MapDiff::Upsert(g, agg.output()),
+                _ => {
+                    self.groups.remove(&g);
+                    MapDiff::Remove(g)
+                }
+            })
+            .collect()
+    }
+}
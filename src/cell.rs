@@ -1,4 +1,7 @@
 use crate::impl_::cell::Cell as CellImpl;
+use crate::impl_::cell::WeakCell as WeakCellImpl;
+use crate::impl_::dep::Trace;
+use crate::impl_::lambda::lambda1_deps;
 use crate::impl_::lambda::IsLambda1;
 use crate::impl_::lambda::IsLambda2;
 use crate::impl_::lambda::IsLambda3;
@@ -6,11 +9,17 @@ use crate::impl_::lambda::IsLambda4;
 use crate::impl_::lambda::IsLambda5;
 use crate::impl_::lambda::IsLambda6;
 use crate::impl_::lazy::Lazy;
+use crate::impl_::node::IsNode;
 use crate::listener::Listener;
 use crate::sodium_ctx::SodiumCtx;
 use crate::stream::Stream;
 use crate::Dep;
 
+use std::borrow::Borrow;
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::ops::{Add, Div, Mul, Neg, Not, Sub};
+
 /// Represents a value of type `A` that changes over time.
 ///
 /// In other Functional Reactive Programming (FRP) systems this is
@@ -21,6 +30,15 @@ pub struct Cell<A> {
     pub impl_: CellImpl<A>,
 }
 
+/// A weak reference to a [`Cell`] that does not keep the cell, or
+/// the part of the FRP graph it depends on, alive.
+///
+/// Use [`upgrade`][WeakCell::upgrade] to get the [`Cell`] back while
+/// it is still alive.
+pub struct WeakCell<A> {
+    pub impl_: WeakCellImpl<A>,
+}
+
 impl<A> Clone for Cell<A> {
     fn clone(&self) -> Self {
         Cell {
@@ -29,6 +47,109 @@ impl<A> Clone for Cell<A> {
     }
 }
 
+impl<A> Clone for WeakCell<A> {
+    fn clone(&self) -> Self {
+        WeakCell {
+            impl_: self.impl_.clone(),
+        }
+    }
+}
+
+impl<A> Trace for Cell<A> {
+    fn trace(&self, deps: &mut Vec<Dep>) {
+        deps.push(self.impl_.to_dep());
+    }
+}
+
+impl<A> WeakCell<A> {
+    /// Attempt to get a strong reference to the [`Cell`], returning
+    /// `None` if it has already been garbage-collected.
+    pub fn upgrade(&self) -> Option<Cell<A>> {
+        self.impl_.upgrade().map(|impl_| Cell { impl_ })
+    }
+}
+
+/// A tuple of references to [`Cell`]s that can be combined by
+/// [`Cell::lift_tuple`].
+///
+/// Implemented for tuples of up to twelve `&Cell`s.
+pub trait CellTuple {
+    /// The tuple of the values of the cells.
+    type Values: Send + 'static;
+
+    #[doc(hidden)]
+    fn lift<R, FN>(self, f: FN) -> CellImpl<R>
+    where
+        R: Clone + Send + 'static,
+        FN: IsLambda1<Self::Values, R> + Send + 'static;
+
+    #[doc(hidden)]
+    fn sampler(self) -> (Vec<Dep>, Sampler<Self::Values>);
+}
+
+#[doc(hidden)]
+pub type Sampler<A> = Box<dyn FnMut() -> A + Send + Sync>;
+
+macro_rules! impl_cell_tuple {
+    ($($t:ident $i:tt),+) => {
+        impl<'a, $($t: Clone + Send + 'static),+> CellTuple for ($(&'a Cell<$t>,)+) {
+            type Values = ($($t,)+);
+
+            fn lift<R, FN>(self, mut f: FN) -> CellImpl<R>
+            where
+                R: Clone + Send + 'static,
+                FN: IsLambda1<Self::Values, R> + Send + 'static,
+            {
+                let sodium_ctx = self.0.impl_.sodium_ctx();
+                let f_deps = lambda1_deps(&f);
+                let updates = ($(self.$i.impl_.updates(),)+);
+                let dependencies = vec![$(updates.$i.box_clone()),+];
+                let mut deps = vec![$(updates.$i.to_dep()),+];
+                deps.extend(f_deps);
+                let state = ($(self.$i.impl_.sample_lazy(),)+);
+                CellImpl::lift_n(
+                    &sodium_ctx,
+                    state,
+                    dependencies,
+                    deps,
+                    move |state: &mut ($(Lazy<$t>,)+)| {
+                        let mut any_firing = false;
+                        $(
+                            updates.$i.with_firing_op(|firing_op: &mut Option<$t>| {
+                                if let Some(ref firing) = firing_op {
+                                    state.$i = Lazy::of_value(firing.clone());
+                                    any_firing = true;
+                                }
+                            });
+                        )+
+                        any_firing
+                    },
+                    move |state: &($(Lazy<$t>,)+)| f.call(&($(state.$i.run(),)+)),
+                )
+            }
+
+            fn sampler(self) -> (Vec<Dep>, Sampler<Self::Values>) {
+                let deps = vec![$(self.$i.to_dep()),+];
+                let cells = ($(self.$i.clone(),)+);
+                (deps, Box::new(move || ($(cells.$i.sample(),)+)))
+            }
+        }
+    };
+}
+
+impl_cell_tuple!(A 0);
+impl_cell_tuple!(A 0, B 1);
+impl_cell_tuple!(A 0, B 1, C 2);
+impl_cell_tuple!(A 0, B 1, C 2, D 3);
+impl_cell_tuple!(A 0, B 1, C 2, D 3, E 4);
+impl_cell_tuple!(A 0, B 1, C 2, D 3, E 4, F 5);
+impl_cell_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_cell_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+impl_cell_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8);
+impl_cell_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9);
+impl_cell_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10);
+impl_cell_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11);
+
 impl<A: Clone + Send + 'static> Cell<A> {
     /// Create a `Cell` with a constant value.
     pub fn new(sodium_ctx: &SodiumCtx, value: A) -> Cell<A> {
@@ -47,7 +168,13 @@ impl<A: Clone + Send + 'static> Cell<A> {
     /// When called within a function passed to [`Stream::map`] using
     /// `sample` is equivalent to [snapshotting][Stream::snapshot]
     /// this `Cell` with that [`Stream`].
+    ///
+    /// With [`SodiumCtx::set_dependency_validation`] enabled, sampling
+    /// a `Cell` that the running function did not declare as a
+    /// dependency is reported.
     pub fn sample(&self) -> A {
+        let node = self.impl_.node();
+        node.sodium_ctx.validate_sample(node);
         self.impl_.sample()
     }
 
@@ -69,6 +196,17 @@ impl<A: Clone + Send + 'static> Cell<A> {
         self.impl_.to_dep()
     }
 
+    /// Create a [`WeakCell`] reference to this `Cell`.
+    ///
+    /// A weak reference does not keep the cell alive, so it can be
+    /// held by I/O code or UI widgets without keeping the FRP graph
+    /// from being garbage-collected.
+    pub fn downgrade(&self) -> WeakCell<A> {
+        WeakCell {
+            impl_: CellImpl::downgrade(&self.impl_),
+        }
+    }
+
     /// Return a [`Stream`] that gives the updates/steps for a `Cell`.
     ///
     /// ## Important
@@ -226,6 +364,108 @@ impl<A: Clone + Send + 'static> Cell<A> {
         }
     }
 
+    /// Lift a function over a tuple of up to twelve cells so the
+    /// returned [`Cell`] always reflects the function applied to a
+    /// tuple of the input cells' values.
+    ///
+    /// Unlike chaining [`lift2`][Cell::lift2] this uses a single node,
+    /// so the function is called once per transaction no matter how
+    /// many of the inputs changed.
+    pub fn lift_tuple<T: CellTuple, FN: IsLambda1<T::Values, A> + Send + 'static>(
+        cells: T,
+        f: FN,
+    ) -> Cell<A> {
+        Cell {
+            impl_: cells.lift(f),
+        }
+    }
+
+    /// Combine a slice of cells into a single `Cell` of a `Vec` of
+    /// their values.
+    ///
+    /// Each distinct input cell gets a small node that tags its
+    /// updates, so a transaction in which some of the cells change
+    /// only replaces their elements of the output, without visiting
+    /// the others. The output `Vec` itself is copied once per such
+    /// transaction, since the resulting cell holds its own value.
+    pub fn sequence(sodium_ctx: &SodiumCtx, cells: &[Cell<A>]) -> Cell<Vec<A>> {
+        let cells: Vec<CellImpl<A>> = cells.iter().map(|c| c.impl_.clone()).collect();
+        Cell {
+            impl_: CellImpl::sequence(&sodium_ctx.impl_, &cells),
+        }
+    }
+
+    /// A variant of [`sequence`][Cell::sequence] where the set of
+    /// cells itself changes over time, in the manner of
+    /// [`switch_c`][Cell::switch_c].
+    ///
+    /// When the set changes, the cells that remain keep their
+    /// existing nodes, and only those added or removed are attached
+    /// or detached.
+    pub fn switch_sequence(cca: &Cell<Vec<Cell<A>>>) -> Cell<Vec<A>> {
+        let cca = cca
+            .map(|cells: &Vec<Cell<A>>| cells.iter().map(|c| c.impl_.clone()).collect::<Vec<_>>());
+        Cell {
+            impl_: CellImpl::switch_sequence(&cca.impl_),
+        }
+    }
+
+    /// Return a stream that fires with the `(old, new)` pair of values
+    /// every time this cell is updated.
+    ///
+    /// The old value is the value of the cell as it was before the
+    /// transaction in which it was updated.
+    pub fn changes(&self) -> Stream<(A, A)> {
+        self.updates()
+            .snapshot(self, |new: &A, old: &A| (old.clone(), new.clone()))
+    }
+
+    /// Return a cell holding the last `n` values of this cell, oldest
+    /// first, including its current value.
+    ///
+    /// The history starts out holding only the current value, and
+    /// grows with each update until it holds `n` values.
+    pub fn history(&self, n: usize) -> Cell<VecDeque<A>> {
+        let init = self.sample_lazy();
+        let init_history = Lazy::new(move || {
+            let mut history = VecDeque::with_capacity(n);
+            if n > 0 {
+                history.push_back(init.run());
+            }
+            history
+        });
+        self.updates()
+            .accum_lazy(init_history, move |a: &A, history: &VecDeque<A>| {
+                let mut history = history.clone();
+                history.push_back(a.clone());
+                while history.len() > n {
+                    history.pop_front();
+                }
+                history
+            })
+    }
+
+    /// Return a cell with the same value as this one, whose
+    /// [`updates`][Cell::updates] only fire when the value has
+    /// actually changed.
+    ///
+    /// Use this on derived cells to stop updates that produce an equal
+    /// value from propagating further through the graph.
+    pub fn calm(&self) -> Cell<A>
+    where
+        A: PartialEq,
+    {
+        self.calm_by(|a: &A, b: &A| a == b)
+    }
+
+    /// A variant of [`calm`][Cell::calm] that uses the specified
+    /// function to decide whether two values are equal.
+    pub fn calm_by<EQ: IsLambda2<A, A, bool> + Send + Sync + 'static>(&self, eq: EQ) -> Cell<A> {
+        self.updates()
+            .calm_lazy(Some(self.sample_lazy()), eq)
+            .hold_lazy(self.sample_lazy())
+    }
+
     /// Unwrap a [`Stream`] in a `Cell` to give a time-varying stream implementation.
     pub fn switch_s(csa: &Cell<Stream<A>>) -> Stream<A> {
         Stream {
@@ -248,6 +488,28 @@ impl<A: Clone + Send + 'static> Cell<A> {
         }
     }
 
+    /// Track the value of this `Cell` under the given name in the
+    /// context's [`History`][crate::History], replacing any cell
+    /// already tracked under it.
+    ///
+    /// The cell is kept alive until it is
+    /// [untracked][crate::History::untrack]. Tracking starts in
+    /// a transaction of its own, which records the cell's current
+    /// value.
+    pub fn track_history(&self, name: &str)
+    where
+        A: Debug,
+    {
+        let history = self.impl_.sodium_ctx().history();
+        let listener = {
+            let history = history.clone();
+            let name = name.to_string();
+            self.impl_
+                .listen(move |a: &A| history.record(&name, format!("{:?}", a)))
+        };
+        history.track(name, listener);
+    }
+
     /// Listen for updates to the value of this `Cell`.
     ///
     /// This is the observer pattern. The returned [`Listener`] has an
@@ -262,3 +524,100 @@ impl<A: Clone + Send + 'static> Cell<A> {
         }
     }
 }
+
+impl Cell<bool> {
+    /// Return a cell that is `true` when both this cell and `cb` are.
+    pub fn and(&self, cb: &Cell<bool>) -> Cell<bool> {
+        self.lift2(cb, |a: &bool, b: &bool| *a && *b)
+    }
+
+    /// Return a cell that is `true` when either this cell or `cb` is.
+    pub fn or(&self, cb: &Cell<bool>) -> Cell<bool> {
+        self.lift2(cb, |a: &bool, b: &bool| *a || *b)
+    }
+
+    /// Return a cell that is `true` when all of the given cells are,
+    /// including when there are none.
+    pub fn all<I>(sodium_ctx: &SodiumCtx, cells: I) -> Cell<bool>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Cell<bool>>,
+    {
+        let cells: Vec<Cell<bool>> = cells.into_iter().map(|c| c.borrow().clone()).collect();
+        Cell::sequence(sodium_ctx, &cells).map(|values: &Vec<bool>| values.iter().all(|a| *a))
+    }
+
+    /// Return a cell that is `true` when any of the given cells is,
+    /// and `false` when there are none.
+    pub fn any<I>(sodium_ctx: &SodiumCtx, cells: I) -> Cell<bool>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Cell<bool>>,
+    {
+        let cells: Vec<Cell<bool>> = cells.into_iter().map(|c| c.borrow().clone()).collect();
+        Cell::sequence(sodium_ctx, &cells).map(|values: &Vec<bool>| values.iter().any(|a| *a))
+    }
+}
+
+macro_rules! impl_cell_unary_op {
+    ($op:ident, $method:ident) => {
+        impl<'a, A> $op for &'a Cell<A>
+        where
+            A: $op + Clone + Send + 'static,
+            A::Output: Clone + Send + 'static,
+        {
+            type Output = Cell<A::Output>;
+
+            fn $method(self) -> Self::Output {
+                self.map(|a: &A| a.clone().$method())
+            }
+        }
+
+        impl<A> $op for Cell<A>
+        where
+            A: $op + Clone + Send + 'static,
+            A::Output: Clone + Send + 'static,
+        {
+            type Output = Cell<A::Output>;
+
+            fn $method(self) -> Self::Output {
+                (&self).$method()
+            }
+        }
+    };
+}
+
+macro_rules! impl_cell_binary_op {
+    ($op:ident, $method:ident) => {
+        impl<'a, 'b, A> $op<&'b Cell<A>> for &'a Cell<A>
+        where
+            A: $op + Clone + Send + 'static,
+            A::Output: Clone + Send + 'static,
+        {
+            type Output = Cell<A::Output>;
+
+            fn $method(self, rhs: &'b Cell<A>) -> Self::Output {
+                self.lift2(rhs, |a: &A, b: &A| a.clone().$method(b.clone()))
+            }
+        }
+
+        impl<A> $op for Cell<A>
+        where
+            A: $op + Clone + Send + 'static,
+            A::Output: Clone + Send + 'static,
+        {
+            type Output = Cell<A::Output>;
+
+            fn $method(self, rhs: Cell<A>) -> Self::Output {
+                (&self).$method(&rhs)
+            }
+        }
+    };
+}
+
+impl_cell_unary_op!(Not, not);
+impl_cell_unary_op!(Neg, neg);
+impl_cell_binary_op!(Add, add);
+impl_cell_binary_op!(Sub, sub);
+impl_cell_binary_op!(Mul, mul);
+impl_cell_binary_op!(Div, div);
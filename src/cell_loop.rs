@@ -1,4 +1,5 @@
 use crate::impl_::cell_loop::CellLoop as CellLoopImpl;
+use crate::impl_::dep::{Dep, Trace};
 use crate::Cell;
 use crate::SodiumCtx;
 
@@ -21,6 +22,12 @@ impl<A> Clone for CellLoop<A> {
     }
 }
 
+impl<A: Send + Clone + 'static> Trace for CellLoop<A> {
+    fn trace(&self, deps: &mut Vec<Dep>) {
+        deps.push(self.impl_.cell().to_dep());
+    }
+}
+
 impl<A: Send + Clone + 'static> CellLoop<A> {
     /// Create a new `CellLoop` in the given context.
     pub fn new(sodium_ctx: &SodiumCtx) -> CellLoop<A> {
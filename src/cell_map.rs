@@ -0,0 +1,503 @@
+use crate::aggregate::{Aggregate, CountAgg, GroupAgg, Grouping, HeapAgg, SumAgg};
+use crate::hash_trie::HashTrie;
+use crate::impl_::lambda::{lambda1, lambda1_deps, IsLambda1};
+use crate::list_tree::ListTree;
+use crate::Cell;
+use crate::CellVec;
+use crate::Lazy;
+use crate::ListDiff;
+use crate::Monoid;
+use crate::Router;
+use crate::SodiumCtx;
+use crate::Stream;
+use crate::These;
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::ops::Sub;
+use std::sync::Mutex;
+
+/// A single edit to a keyed map, as carried by the diffs of a
+/// [`CellMap`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MapDiff<K, V> {
+    /// Insert a value for the key, replacing any existing one.
+    Upsert(K, V),
+    /// Remove the key and its value, if present.
+    Remove(K),
+}
+
+impl<K, V> MapDiff<K, V> {
+    /// The key this edit applies to.
+    pub fn key(&self) -> &K {
+        match self {
+            MapDiff::Upsert(k, _) | MapDiff::Remove(k) => k,
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> MapDiff<K, V> {
+    /// Apply this edit to a `HashMap`.
+    pub fn apply(&self, map: &mut HashMap<K, V>) {
+        match self {
+            MapDiff::Upsert(k, v) => {
+                map.insert(k.clone(), v.clone());
+            }
+            MapDiff::Remove(k) => {
+                map.remove(k);
+            }
+        }
+    }
+}
+
+type JoinDiffs<K, V, W> = These<Vec<MapDiff<K, V>>, Vec<MapDiff<K, W>>>;
+
+/// A reactive `HashMap`, represented as a cell holding its contents
+/// plus a stream of the edits made to it in each transaction.
+///
+/// The contents are kept in a trie that shares its unchanged parts
+/// between versions, so applying an edit costs the same however many
+/// keys the map holds.
+///
+/// Observers of a single key use [`get`][CellMap::get], which only
+/// updates when that key is edited. As with a [`Router`], the
+/// per-key streams are created on demand and only held weakly, so
+/// they are freed once nothing observes them.
+pub struct CellMap<K, V> {
+    diffs: Stream<Vec<MapDiff<K, V>>>,
+    router: Router<Vec<MapDiff<K, V>>, K>,
+    contents: Cell<HashTrie<K, V>>,
+}
+
+impl<K, V> Clone for CellMap<K, V> {
+    fn clone(&self) -> Self {
+        CellMap {
+            diffs: self.diffs.clone(),
+            router: self.router.clone(),
+            contents: self.contents.clone(),
+        }
+    }
+}
+
+impl<K, V> CellMap<K, V>
+where
+    K: Clone + Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Create a `CellMap` with the given initial contents, which is
+    /// updated by each batch of edits on `diffs`.
+    pub fn new(initial: HashMap<K, V>, diffs: &Stream<Vec<MapDiff<K, V>>>) -> CellMap<K, V> {
+        let mut trie = HashTrie::new();
+        for (k, v) in initial {
+            trie.insert(k, v);
+        }
+        CellMap::from_trie(trie, diffs)
+    }
+
+    fn from_trie(trie: HashTrie<K, V>, diffs: &Stream<Vec<MapDiff<K, V>>>) -> CellMap<K, V> {
+        let contents = diffs.accum(trie, |diffs: &Vec<MapDiff<K, V>>, trie: &HashTrie<K, V>| {
+            let mut trie = trie.clone();
+            for diff in diffs {
+                match diff {
+                    MapDiff::Upsert(k, v) => {
+                        trie.insert(k.clone(), v.clone());
+                    }
+                    MapDiff::Remove(k) => {
+                        trie.remove(k);
+                    }
+                }
+            }
+            trie
+        });
+        let sodium_ctx = SodiumCtx {
+            impl_: diffs.impl_.sodium_ctx(),
+        };
+        let router = Router::new(&sodium_ctx, diffs, |diffs: &Vec<MapDiff<K, V>>| {
+            diffs.iter().map(|diff| diff.key().clone()).collect()
+        });
+        CellMap {
+            diffs: diffs.clone(),
+            router,
+            contents,
+        }
+    }
+
+    /// The stream of edits made to this map, one batch per
+    /// transaction.
+    pub fn diffs(&self) -> Stream<Vec<MapDiff<K, V>>> {
+        self.diffs.clone()
+    }
+
+    /// Sample the current contents of this map.
+    pub fn sample(&self) -> HashMap<K, V> {
+        self.contents.sample().to_hash_map()
+    }
+
+    /// A cell holding the contents of this map.
+    ///
+    /// This cell copies the whole map out on every edit to any key, so
+    /// prefer [`get`][CellMap::get] for observing individual keys.
+    pub fn cell(&self) -> Cell<HashMap<K, V>> {
+        self.contents
+            .map(|trie: &HashTrie<K, V>| trie.to_hash_map())
+    }
+
+    /// A cell holding the value for the given key, or `None` while the
+    /// key is absent. It only updates when that key is edited.
+    pub fn get(&self, k: &K) -> Cell<Option<V>> {
+        let init = {
+            let contents = self.contents.sample_lazy();
+            let k = k.clone();
+            Lazy::new(move || contents.run().get(&k).cloned())
+        };
+        let k = k.clone();
+        self.router
+            .filter_matches(&k)
+            .map(move |diffs: &Vec<MapDiff<K, V>>| {
+                diffs
+                    .iter()
+                    .filter(|diff| *diff.key() == k)
+                    .last()
+                    .and_then(|diff| match diff {
+                        MapDiff::Upsert(_, v) => Some(v.clone()),
+                        MapDiff::Remove(_) => None,
+                    })
+            })
+            .hold_lazy(init)
+    }
+
+    /// A reactive list of the keys in this map, in the order in which
+    /// they were first inserted.
+    ///
+    /// The keys present when this is called are listed first, in an
+    /// unspecified order.
+    pub fn keys(&self) -> CellVec<K> {
+        let initial: Vec<K> = self.sample().into_keys().collect();
+        let mut order = KeyOrder::new();
+        for k in &initial {
+            order.insert(k);
+        }
+        let order = Mutex::new(order);
+        let key_diffs = self.diffs.filter_map(move |diffs: &Vec<MapDiff<K, V>>| {
+            let mut order = order.lock().unwrap();
+            let mut out = Vec::new();
+            for diff in diffs {
+                match diff {
+                    MapDiff::Upsert(k, _) => {
+                        if let Some(i) = order.insert(k) {
+                            out.push(ListDiff::Insert(i, k.clone()));
+                        }
+                    }
+                    MapDiff::Remove(k) => {
+                        if let Some(i) = order.remove(k) {
+                            out.push(ListDiff::Remove(i));
+                        }
+                    }
+                }
+            }
+            if out.is_empty() {
+                None
+            } else {
+                Some(out)
+            }
+        });
+        CellVec::new(initial, &key_diffs)
+    }
+
+    /// A cell holding the number of keys in this map.
+    ///
+    /// The count is kept with the contents, which add one for each
+    /// upsert of a new key and subtract one for each removal of a
+    /// present key.
+    pub fn len(&self) -> Cell<usize> {
+        self.contents.map(|trie: &HashTrie<K, V>| trie.len())
+    }
+
+    /// Return a `CellMap` with the function applied to each value.
+    ///
+    /// The function is applied to every value once when the returned
+    /// map is created, and after that once to each upserted value.
+    pub fn map_values<W, FN>(&self, mut f: FN) -> CellMap<K, W>
+    where
+        W: Clone + Send + Sync + 'static,
+        FN: IsLambda1<V, W> + Send + Sync + 'static,
+    {
+        let mut trie = HashTrie::new();
+        for (k, v) in self.sample() {
+            trie.insert(k, f.call(&v));
+        }
+        let f_deps = lambda1_deps(&f);
+        let f = Mutex::new(f);
+        let diffs = self.diffs.map(lambda1(
+            move |diffs: &Vec<MapDiff<K, V>>| {
+                let mut f = f.lock().unwrap();
+                diffs
+                    .iter()
+                    .map(|diff| match diff {
+                        MapDiff::Upsert(k, v) => MapDiff::Upsert(k.clone(), f.call(v)),
+                        MapDiff::Remove(k) => MapDiff::Remove(k.clone()),
+                    })
+                    .collect()
+            },
+            f_deps,
+        ));
+        CellMap::from_trie(trie, &diffs)
+    }
+
+    /// Group the entries of this map by the key that the function
+    /// returns for each value.
+    ///
+    /// The returned map holds, for each group, the entries of this map
+    /// that belong to it. Each batch of edits to this map only emits
+    /// edits for the groups it touches, and a group is removed once its
+    /// last entry is.
+    pub fn group_by<G, GF>(&self, group_fn: GF) -> CellMap<G, HashMap<K, V>>
+    where
+        G: Clone + Eq + Hash + Send + Sync + 'static,
+        GF: IsLambda1<V, G> + Send + Sync + 'static,
+    {
+        self.aggregate(group_fn, |v: &V| v.clone(), GroupAgg::new)
+    }
+
+    /// Count the entries of this map in each group, grouping by the key
+    /// that the function returns for each value.
+    pub fn count_by<G, GF>(&self, group_fn: GF) -> CellMap<G, usize>
+    where
+        G: Clone + Eq + Hash + Send + Sync + 'static,
+        GF: IsLambda1<V, G> + Send + Sync + 'static,
+    {
+        self.aggregate(group_fn, |_: &V| (), CountAgg::new)
+    }
+
+    /// Sum `value_fn` over the entries of this map in each group,
+    /// grouping by the key that `group_fn` returns for each value.
+    ///
+    /// The sums are updated by adding the values of upserted entries
+    /// and subtracting those of the entries they replace or that are
+    /// removed.
+    pub fn sum_by<G, S, GF, SF>(&self, group_fn: GF, value_fn: SF) -> CellMap<G, S>
+    where
+        G: Clone + Eq + Hash + Send + Sync + 'static,
+        S: Monoid + Sub<Output = S> + Clone + Send + Sync + 'static,
+        GF: IsLambda1<V, G> + Send + Sync + 'static,
+        SF: IsLambda1<V, S> + Send + Sync + 'static,
+    {
+        self.aggregate(group_fn, value_fn, SumAgg::new)
+    }
+
+    /// The least of `value_fn` over the entries of this map in each
+    /// group, grouping by the key that `group_fn` returns for each
+    /// value.
+    ///
+    /// Each group keeps its values in a binary heap, so removing the
+    /// least value does not rescan the group.
+    pub fn min_by<G, X, GF, XF>(&self, group_fn: GF, value_fn: XF) -> CellMap<G, X>
+    where
+        G: Clone + Eq + Hash + Send + Sync + 'static,
+        X: Ord + Clone + Send + Sync + 'static,
+        GF: IsLambda1<V, G> + Send + Sync + 'static,
+        XF: IsLambda1<V, X> + Send + Sync + 'static,
+    {
+        let value_deps = lambda1_deps(&value_fn);
+        let mut value_fn = value_fn;
+        let value_fn = lambda1(move |v: &V| Reverse(value_fn.call(v)), value_deps);
+        self.aggregate(group_fn, value_fn, HeapAgg::new)
+            .map_values(|x: &Reverse<X>| x.0.clone())
+    }
+
+    /// The greatest of `value_fn` over the entries of this map in each
+    /// group, grouping by the key that `group_fn` returns for each
+    /// value.
+    ///
+    /// Each group keeps its values in a binary heap, so removing the
+    /// greatest value does not rescan the group.
+    pub fn max_by<G, X, GF, XF>(&self, group_fn: GF, value_fn: XF) -> CellMap<G, X>
+    where
+        G: Clone + Eq + Hash + Send + Sync + 'static,
+        X: Ord + Clone + Send + Sync + 'static,
+        GF: IsLambda1<V, G> + Send + Sync + 'static,
+        XF: IsLambda1<V, X> + Send + Sync + 'static,
+    {
+        self.aggregate(group_fn, value_fn, HeapAgg::new)
+    }
+
+    fn aggregate<G, X, AGG, GF, XF>(
+        &self,
+        group_fn: GF,
+        value_fn: XF,
+        new_agg: fn() -> AGG,
+    ) -> CellMap<G, AGG::Output>
+    where
+        G: Clone + Eq + Hash + Send + Sync + 'static,
+        X: Clone + Send + 'static,
+        AGG: Aggregate<K, X> + Send + 'static,
+        AGG::Output: Clone + Send + Sync + 'static,
+        GF: IsLambda1<V, G> + Send + Sync + 'static,
+        XF: IsLambda1<V, X> + Send + Sync + 'static,
+    {
+        let mut deps = lambda1_deps(&group_fn);
+        deps.extend(lambda1_deps(&value_fn));
+        let mut grouping = Grouping::new(group_fn, value_fn, new_agg);
+        for (k, v) in self.sample() {
+            grouping.insert(&k, &v);
+        }
+        let initial = grouping.outputs();
+        let grouping = Mutex::new(grouping);
+        let diffs = self.diffs.filter_map(lambda1(
+            move |diffs: &Vec<MapDiff<K, V>>| {
+                let out = grouping.lock().unwrap().apply(diffs);
+                if out.is_empty() {
+                    None
+                } else {
+                    Some(out)
+                }
+            },
+            deps,
+        ));
+        CellMap::new(initial, &diffs)
+    }
+
+    /// The inner join of this map with another: the pairs of values of
+    /// the keys present in both.
+    ///
+    /// Each batch of edits to either map only emits edits for the keys
+    /// it touches. Both maps are copied to look up the other side of
+    /// an edited key.
+    pub fn join<W>(&self, other: &CellMap<K, W>) -> CellMap<K, (V, W)>
+    where
+        W: Clone + Send + Sync + 'static,
+    {
+        self.join_with(other, |v, w| Some((v?.clone(), w?.clone())))
+    }
+
+    /// The left join of this map with another: the values of this map
+    /// paired with the value of the same key in `other`, if any.
+    ///
+    /// Like [`join`][CellMap::join], this only emits edits for the
+    /// keys that either map edits.
+    pub fn left_join<W>(&self, other: &CellMap<K, W>) -> CellMap<K, (V, Option<W>)>
+    where
+        W: Clone + Send + Sync + 'static,
+    {
+        self.join_with(other, |v, w| Some((v?.clone(), w.cloned())))
+    }
+
+    fn join_with<W, R>(
+        &self,
+        other: &CellMap<K, W>,
+        f: fn(Option<&V>, Option<&W>) -> Option<R>,
+    ) -> CellMap<K, R>
+    where
+        W: Clone + Send + Sync + 'static,
+        R: Clone + Send + Sync + 'static,
+    {
+        let left = self.sample();
+        let right = other.sample();
+        let initial: HashMap<K, R> = left
+            .keys()
+            .chain(right.keys())
+            .filter_map(|k| Some((k.clone(), f(left.get(k), right.get(k))?)))
+            .collect();
+        // The keys present in the joined map, so that a key is only
+        // reported removed if it was there.
+        let joined: HashSet<K> = initial.keys().cloned().collect();
+        let sides = Mutex::new((left, right, joined));
+        let diffs =
+            self.diffs
+                .merge_with(&other.diffs)
+                .filter_map(move |diffs: &JoinDiffs<K, V, W>| {
+                    let mut sides = sides.lock().unwrap();
+                    let (left, right, joined) = &mut *sides;
+                    let mut touched = Vec::new();
+                    let mut seen = HashSet::new();
+                    if let Some(diffs) = diffs.this() {
+                        for diff in diffs {
+                            diff.apply(left);
+                            if seen.insert(diff.key().clone()) {
+                                touched.push(diff.key().clone());
+                            }
+                        }
+                    }
+                    if let Some(diffs) = diffs.that() {
+                        for diff in diffs {
+                            diff.apply(right);
+                            if seen.insert(diff.key().clone()) {
+                                touched.push(diff.key().clone());
+                            }
+                        }
+                    }
+                    let out: Vec<MapDiff<K, R>> = touched
+                        .into_iter()
+                        .filter_map(|k| match f(left.get(&k), right.get(&k)) {
+                            Some(r) => {
+                                joined.insert(k.clone());
+                                Some(MapDiff::Upsert(k, r))
+                            }
+                            None => {
+                                if joined.remove(&k) {
+                                    Some(MapDiff::Remove(k))
+                                } else {
+                                    None
+                                }
+                            }
+                        })
+                        .collect();
+                    if out.is_empty() {
+                        None
+                    } else {
+                        Some(out)
+                    }
+                });
+        CellMap::new(initial, &diffs)
+    }
+}
+
+// The keys of a map in insertion order. Each key takes a slot when it
+// is inserted, and a removed key leaves its slot empty, so the index of
+// a key in the list is the number of occupied slots before its own.
+// The slots are compacted once most of them are empty.
+struct KeyOrder<K> {
+    slots: ListTree<Option<K>>,
+    positions: HashMap<K, usize>,
+}
+
+impl<K: Clone + Eq + Hash> KeyOrder<K> {
+    fn new() -> KeyOrder<K> {
+        KeyOrder {
+            slots: ListTree::new(),
+            positions: HashMap::new(),
+        }
+    }
+
+    // Insert the key if it is absent, returning its index.
+    fn insert(&mut self, k: &K) -> Option<usize> {
+        if self.positions.contains_key(k) {
+            return None;
+        }
+        let slot = self.slots.len();
+        self.slots.push(Some(k.clone()), 1);
+        self.positions.insert(k.clone(), slot);
+        Some(self.positions.len() - 1)
+    }
+
+    // Remove the key if it is present, returning its former index.
+    fn remove(&mut self, k: &K) -> Option<usize> {
+        let slot = self.positions.remove(k)?;
+        let i = self.slots.weight_before(slot);
+        self.slots.set(slot, None, 0);
+        if self.slots.len() > 2 * self.positions.len() + 16 {
+            self.compact();
+        }
+        Some(i)
+    }
+
+    fn compact(&mut self) {
+        let keys = self.slots.to_vec();
+        self.slots = ListTree::new();
+        for k in keys.into_iter().flatten() {
+            self.positions.insert(k.clone(), self.slots.len());
+            self.slots.push(Some(k), 1);
+        }
+    }
+}
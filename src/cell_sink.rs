@@ -1,5 +1,7 @@
 use crate::cell::Cell;
 use crate::impl_::cell_sink::CellSink as CellSinkImpl;
+use crate::impl_::cell_sink::WeakCellSink as WeakCellSinkImpl;
+use crate::impl_::dep::{Dep, Trace};
 use crate::sodium_ctx::SodiumCtx;
 
 /// A [`Cell`] that allows values to be pushed into it, acting as a
@@ -11,6 +13,15 @@ pub struct CellSink<A> {
     pub impl_: CellSinkImpl<A>,
 }
 
+/// A weak reference to a [`CellSink`] that does not keep the sink
+/// alive.
+///
+/// This allows I/O callbacks to stop sending once the FRP graph they
+/// feed has been dropped.
+pub struct WeakCellSink<A> {
+    pub impl_: WeakCellSinkImpl<A>,
+}
+
 impl<A> Clone for CellSink<A> {
     fn clone(&self) -> Self {
         CellSink {
@@ -19,6 +30,28 @@ impl<A> Clone for CellSink<A> {
     }
 }
 
+impl<A> Clone for WeakCellSink<A> {
+    fn clone(&self) -> Self {
+        WeakCellSink {
+            impl_: self.impl_.clone(),
+        }
+    }
+}
+
+impl<A: Send + Clone + 'static> Trace for CellSink<A> {
+    fn trace(&self, deps: &mut Vec<Dep>) {
+        deps.push(self.impl_.cell().to_dep());
+    }
+}
+
+impl<A> WeakCellSink<A> {
+    /// Attempt to get a strong reference to the [`CellSink`],
+    /// returning `None` if it has already been garbage-collected.
+    pub fn upgrade(&self) -> Option<CellSink<A>> {
+        self.impl_.upgrade().map(|impl_| CellSink { impl_ })
+    }
+}
+
 impl<A: Clone + Send + 'static> CellSink<A> {
     /// Create a new `CellSink` in the given context.
     pub fn new(sodium_ctx: &SodiumCtx, a: A) -> CellSink<A> {
@@ -47,4 +80,11 @@ impl<A: Clone + Send + 'static> CellSink<A> {
     pub fn send(&self, a: A) {
         self.impl_.send(a);
     }
+
+    /// Create a [`WeakCellSink`] reference to this `CellSink`.
+    pub fn downgrade(&self) -> WeakCellSink<A> {
+        WeakCellSink {
+            impl_: CellSinkImpl::downgrade(&self.impl_),
+        }
+    }
 }
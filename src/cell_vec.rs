@@ -0,0 +1,304 @@
+use crate::impl_::lambda::{lambda1, lambda1_deps, IsLambda1};
+use crate::list_tree::ListTree;
+use crate::Cell;
+use crate::Monoid;
+use crate::Stream;
+
+use std::ops::Sub;
+use std::sync::Mutex;
+
+/// A single edit to a list, as carried by the diffs of a
+/// [`CellVec`].
+///
+/// Indices refer to the list as it is after the preceding edits in
+/// the same batch have been applied.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ListDiff<A> {
+    /// Insert a value so that it ends up at the given index.
+    Insert(usize, A),
+    /// Remove the value at the given index.
+    Remove(usize),
+    /// Replace the value at the given index.
+    Replace(usize, A),
+    /// Remove the value at `from` and insert it again so that it ends
+    /// up at `to`.
+    Move { from: usize, to: usize },
+}
+
+impl<A: Clone> ListDiff<A> {
+    /// Apply this edit to a `Vec`.
+    pub fn apply(&self, v: &mut Vec<A>) {
+        match self {
+            ListDiff::Insert(i, a) => v.insert(*i, a.clone()),
+            ListDiff::Remove(i) => {
+                v.remove(*i);
+            }
+            ListDiff::Replace(i, a) => v[*i] = a.clone(),
+            ListDiff::Move { from, to } => {
+                let a = v.remove(*from);
+                v.insert(*to, a);
+            }
+        }
+    }
+}
+
+/// A reactive list, represented as a cell holding its contents plus
+/// a stream of the edits made to it in each transaction.
+///
+/// The contents are kept in a tree that shares its unchanged parts
+/// between versions, so applying an edit costs O(log n) in the length
+/// of the list rather than a copy of the whole list. Operators such as
+/// [`map`][CellVec::map] and [`filter`][CellVec::filter] work on the
+/// edits alone, so a change to one element costs the same downstream
+/// however long the list is.
+pub struct CellVec<A> {
+    diffs: Stream<Vec<ListDiff<A>>>,
+    contents: Cell<ListTree<A>>,
+}
+
+impl<A> Clone for CellVec<A> {
+    fn clone(&self) -> Self {
+        CellVec {
+            diffs: self.diffs.clone(),
+            contents: self.contents.clone(),
+        }
+    }
+}
+
+impl<A: Clone + Send + Sync + 'static> CellVec<A> {
+    /// Create a `CellVec` with the given initial contents, which is
+    /// updated by each batch of edits on `diffs`.
+    pub fn new(initial: Vec<A>, diffs: &Stream<Vec<ListDiff<A>>>) -> CellVec<A> {
+        let mut tree = ListTree::new();
+        for a in initial {
+            tree.push(a, 0);
+        }
+        CellVec::from_tree(tree, diffs)
+    }
+
+    fn from_tree(tree: ListTree<A>, diffs: &Stream<Vec<ListDiff<A>>>) -> CellVec<A> {
+        let contents = diffs.accum(tree, |diffs: &Vec<ListDiff<A>>, tree: &ListTree<A>| {
+            let mut tree = tree.clone();
+            for diff in diffs {
+                apply_to_tree(diff, &mut tree);
+            }
+            tree
+        });
+        CellVec {
+            diffs: diffs.clone(),
+            contents,
+        }
+    }
+
+    /// The stream of edits made to this list, one batch per
+    /// transaction.
+    pub fn diffs(&self) -> Stream<Vec<ListDiff<A>>> {
+        self.diffs.clone()
+    }
+
+    /// Sample the current contents of this list.
+    pub fn sample(&self) -> Vec<A> {
+        self.contents.sample().to_vec()
+    }
+
+    /// A cell holding the contents of this list.
+    ///
+    /// The returned cell copies the whole list out in every
+    /// transaction where it changes, so prefer working with
+    /// [`diffs`][CellVec::diffs] where possible.
+    pub fn cell(&self) -> Cell<Vec<A>> {
+        self.contents.map(|tree: &ListTree<A>| tree.to_vec())
+    }
+
+    /// Return a `CellVec` with the function applied to each element.
+    ///
+    /// The function is applied to every element once when the
+    /// returned list is created, and after that once to each inserted
+    /// or replaced element.
+    pub fn map<B, FN>(&self, mut f: FN) -> CellVec<B>
+    where
+        B: Clone + Send + Sync + 'static,
+        FN: IsLambda1<A, B> + Send + Sync + 'static,
+    {
+        let mut tree = ListTree::new();
+        for a in self.contents.sample().to_vec() {
+            tree.push(f.call(&a), 0);
+        }
+        let f_deps = lambda1_deps(&f);
+        let f = Mutex::new(f);
+        let diffs = self.diffs.map(lambda1(
+            move |diffs: &Vec<ListDiff<A>>| {
+                let mut f = f.lock().unwrap();
+                diffs
+                    .iter()
+                    .map(|diff| match diff {
+                        ListDiff::Insert(i, a) => ListDiff::Insert(*i, f.call(a)),
+                        ListDiff::Remove(i) => ListDiff::Remove(*i),
+                        ListDiff::Replace(i, a) => ListDiff::Replace(*i, f.call(a)),
+                        ListDiff::Move { from, to } => ListDiff::Move {
+                            from: *from,
+                            to: *to,
+                        },
+                    })
+                    .collect()
+            },
+            f_deps,
+        ));
+        CellVec::from_tree(tree, &diffs)
+    }
+
+    /// Return a `CellVec` holding only the elements for which the
+    /// predicate returns `true`.
+    ///
+    /// The predicate is evaluated once for each element when the
+    /// returned list is created, and after that once for each
+    /// inserted or replaced element. To translate indices, the
+    /// returned list keeps a tree of flags, one per element of this
+    /// list.
+    pub fn filter<PRED>(&self, mut pred: PRED) -> CellVec<A>
+    where
+        PRED: IsLambda1<A, bool> + Send + Sync + 'static,
+    {
+        let mut tree = ListTree::new();
+        let mut passes = ListTree::new();
+        for a in self.contents.sample().to_vec() {
+            let pass = pred.call(&a);
+            passes.push((), pass as usize);
+            if pass {
+                tree.push(a, 0);
+            }
+        }
+        let pred_deps = lambda1_deps(&pred);
+        let state = Mutex::new((pred, passes));
+        let diffs = self.diffs.filter_map(lambda1(
+            move |diffs: &Vec<ListDiff<A>>| {
+                let mut l = state.lock().unwrap();
+                let (pred, passes) = &mut *l;
+                let mut out = Vec::new();
+                for diff in diffs {
+                    filter_diff(pred, passes, diff, &mut out);
+                }
+                if out.is_empty() {
+                    None
+                } else {
+                    Some(out)
+                }
+            },
+            pred_deps,
+        ));
+        CellVec::from_tree(tree, &diffs)
+    }
+
+    /// A cell holding the number of elements in this list.
+    pub fn len(&self) -> Cell<usize> {
+        self.contents.map(|tree: &ListTree<A>| tree.len())
+    }
+
+    /// A cell holding the sum of the elements in this list.
+    ///
+    /// The sum is a running total: each batch of edits adds the
+    /// inserted values and subtracts the removed ones, looking the
+    /// removed values up in the contents as they were before the
+    /// batch.
+    pub fn sum(&self) -> Cell<A>
+    where
+        A: Monoid + Sub<Output = A>,
+    {
+        let init = self
+            .contents
+            .sample()
+            .to_vec()
+            .iter()
+            .fold(A::empty(), |sum, a| sum.combine(a));
+        let changes = self.diffs.snapshot(
+            &self.contents,
+            |diffs: &Vec<ListDiff<A>>, tree: &ListTree<A>| {
+                let mut tree = tree.clone();
+                let mut added = A::empty();
+                let mut removed = A::empty();
+                for diff in diffs {
+                    match diff {
+                        ListDiff::Insert(_, a) => added = added.combine(a),
+                        ListDiff::Remove(i) => removed = removed.combine(tree.get(*i)),
+                        ListDiff::Replace(i, a) => {
+                            added = added.combine(a);
+                            removed = removed.combine(tree.get(*i));
+                        }
+                        ListDiff::Move { .. } => (),
+                    }
+                    apply_to_tree(diff, &mut tree);
+                }
+                (added, removed)
+            },
+        );
+        changes.accum(init, |(added, removed): &(A, A), sum: &A| {
+            sum.combine(added) - removed.clone()
+        })
+    }
+}
+
+fn apply_to_tree<A: Clone>(diff: &ListDiff<A>, tree: &mut ListTree<A>) {
+    match diff {
+        ListDiff::Insert(i, a) => tree.insert(*i, a.clone(), 0),
+        ListDiff::Remove(i) => {
+            tree.remove(*i);
+        }
+        ListDiff::Replace(i, a) => {
+            tree.set(*i, a.clone(), 0);
+        }
+        ListDiff::Move { from, to } => {
+            let (a, _) = tree.remove(*from);
+            tree.insert(*to, a, 0);
+        }
+    }
+}
+
+// Translate one edit of the source list into the edit of the filtered
+// list, if any. `passes` holds one element per element of the source
+// list, weighted 1 if it passes the predicate, so the index of an
+// element in the filtered list is the weight before it.
+fn filter_diff<A: Clone, PRED: IsLambda1<A, bool>>(
+    pred: &mut PRED,
+    passes: &mut ListTree<()>,
+    diff: &ListDiff<A>,
+    out: &mut Vec<ListDiff<A>>,
+) {
+    match diff {
+        ListDiff::Insert(i, a) => {
+            let pass = pred.call(a);
+            passes.insert(*i, (), pass as usize);
+            if pass {
+                out.push(ListDiff::Insert(passes.weight_before(*i), a.clone()));
+            }
+        }
+        ListDiff::Remove(i) => {
+            let (_, pass) = passes.remove(*i);
+            if pass == 1 {
+                out.push(ListDiff::Remove(passes.weight_before(*i)));
+            }
+        }
+        ListDiff::Replace(i, a) => {
+            let pass = pred.call(a);
+            let (_, old_pass) = passes.set(*i, (), pass as usize);
+            let j = passes.weight_before(*i);
+            match (old_pass == 1, pass) {
+                (true, true) => out.push(ListDiff::Replace(j, a.clone())),
+                (true, false) => out.push(ListDiff::Remove(j)),
+                (false, true) => out.push(ListDiff::Insert(j, a.clone())),
+                (false, false) => (),
+            }
+        }
+        ListDiff::Move { from, to } => {
+            let (_, pass) = passes.remove(*from);
+            let j_from = passes.weight_before(*from);
+            passes.insert(*to, (), pass);
+            let j_to = passes.weight_before(*to);
+            if pass == 1 && j_from != j_to {
+                out.push(ListDiff::Move {
+                    from: j_from,
+                    to: j_to,
+                });
+            }
+        }
+    }
+}
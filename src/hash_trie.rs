@@ -0,0 +1,186 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+
+// A hash map stored as a trie over the hashes of its keys, 16 ways at
+// each level, whose nodes are shared through `Arc`s. Cloning a
+// `HashTrie` is O(1), and editing a clone only copies the nodes on the
+// path to the edited key, so a cell can hold one version while the
+// next is built from it.
+pub(crate) struct HashTrie<K, V> {
+    root: Link<K, V>,
+    len: usize,
+    hasher: RandomState,
+}
+
+type Link<K, V> = Option<Arc<TrieNode<K, V>>>;
+
+const BITS: u32 = 4;
+const WIDTH: usize = 1 << BITS;
+
+#[derive(Clone)]
+enum TrieNode<K, V> {
+    Branch([Link<K, V>; WIDTH]),
+    // The entries whose keys share this full hash.
+    Leaf(u64, Vec<(K, V)>),
+}
+
+impl<K, V> Clone for HashTrie<K, V> {
+    fn clone(&self) -> Self {
+        HashTrie {
+            root: self.root.clone(),
+            len: self.len,
+            hasher: self.hasher.clone(),
+        }
+    }
+}
+
+fn slot(hash: u64, shift: u32) -> usize {
+    ((hash >> shift) as usize) & (WIDTH - 1)
+}
+
+fn insert<K: Clone + Eq, V: Clone>(
+    link: &mut Link<K, V>,
+    hash: u64,
+    shift: u32,
+    k: K,
+    v: V,
+) -> Option<V> {
+    let node = match link {
+        None => {
+            *link = Some(Arc::new(TrieNode::Leaf(hash, vec![(k, v)])));
+            return None;
+        }
+        Some(node) => Arc::make_mut(node),
+    };
+    if let TrieNode::Leaf(leaf_hash, entries) = node {
+        if *leaf_hash == hash {
+            for entry in entries.iter_mut() {
+                if entry.0 == k {
+                    return Some(std::mem::replace(&mut entry.1, v));
+                }
+            }
+            entries.push((k, v));
+            return None;
+        }
+        // Push the leaf down a level to make room for the new key.
+        let mut children: [Link<K, V>; WIDTH] = Default::default();
+        let leaf_slot = slot(*leaf_hash, shift);
+        children[leaf_slot] = Some(Arc::new(TrieNode::Leaf(
+            *leaf_hash,
+            std::mem::take(entries),
+        )));
+        *node = TrieNode::Branch(children);
+    }
+    match node {
+        TrieNode::Branch(children) => {
+            insert(&mut children[slot(hash, shift)], hash, shift + BITS, k, v)
+        }
+        TrieNode::Leaf(_, _) => unreachable!(),
+    }
+}
+
+fn remove<K: Clone + Eq, V: Clone>(
+    link: &mut Link<K, V>,
+    hash: u64,
+    shift: u32,
+    k: &K,
+) -> Option<V> {
+    let node = Arc::make_mut(link.as_mut()?);
+    let (result, now_empty) = match node {
+        TrieNode::Leaf(leaf_hash, entries) => {
+            if *leaf_hash != hash {
+                return None;
+            }
+            let i = entries.iter().position(|entry| entry.0 == *k)?;
+            let (_, v) = entries.swap_remove(i);
+            (Some(v), entries.is_empty())
+        }
+        TrieNode::Branch(children) => {
+            let result = remove(&mut children[slot(hash, shift)], hash, shift + BITS, k);
+            (result, children.iter().all(Option::is_none))
+        }
+    };
+    if now_empty {
+        *link = None;
+    }
+    result
+}
+
+fn push_entries<K: Clone + Eq + Hash, V: Clone>(link: &Link<K, V>, out: &mut HashMap<K, V>) {
+    match link.as_deref() {
+        None => (),
+        Some(TrieNode::Leaf(_, entries)) => out.extend(entries.iter().cloned()),
+        Some(TrieNode::Branch(children)) => {
+            for child in children {
+                push_entries(child, out);
+            }
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> HashTrie<K, V> {
+    pub(crate) fn new() -> HashTrie<K, V> {
+        HashTrie {
+            root: None,
+            len: 0,
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn hash(&self, k: &K) -> u64 {
+        self.hasher.hash_one(k)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn get(&self, k: &K) -> Option<&V> {
+        let hash = self.hash(k);
+        let mut link = &self.root;
+        let mut shift = 0;
+        loop {
+            match link.as_deref()? {
+                TrieNode::Leaf(leaf_hash, entries) => {
+                    if *leaf_hash != hash {
+                        return None;
+                    }
+                    return entries
+                        .iter()
+                        .find(|entry| entry.0 == *k)
+                        .map(|entry| &entry.1);
+                }
+                TrieNode::Branch(children) => {
+                    link = &children[slot(hash, shift)];
+                    shift += BITS;
+                }
+            }
+        }
+    }
+
+    pub(crate) fn insert(&mut self, k: K, v: V) -> Option<V> {
+        let hash = self.hash(&k);
+        let old = insert(&mut self.root, hash, 0, k, v);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    pub(crate) fn remove(&mut self, k: &K) -> Option<V> {
+        let hash = self.hash(k);
+        let old = remove(&mut self.root, hash, 0, k);
+        if old.is_some() {
+            self.len -= 1;
+        }
+        old
+    }
+
+    pub(crate) fn to_hash_map(&self) -> HashMap<K, V> {
+        let mut result = HashMap::with_capacity(self.len);
+        push_entries(&self.root, &mut result);
+        result
+    }
+}
@@ -0,0 +1,126 @@
+use crate::impl_::history::History as HistoryImpl;
+use crate::impl_::history::HistoryValues;
+
+use std::collections::BTreeMap;
+
+/// A record of the values that named cells held after each of the
+/// most recent transactions of a [`SodiumCtx`][crate::SodiumCtx].
+///
+/// This is a debugging aid, and is off until enabled with
+/// [`SodiumCtx::set_history_capacity`]. Cells are added to it with
+/// [`Cell::track_history`], and their values are kept as their
+/// `Debug` representations.
+///
+/// Every transaction is recorded while history is enabled, including
+/// those that changed no tracked cell, so that any transaction id can
+/// be looked up.
+///
+/// [`SodiumCtx::set_history_capacity`]: crate::SodiumCtx::set_history_capacity
+/// [`Cell::track_history`]: crate::Cell::track_history
+#[derive(Clone)]
+pub struct History {
+    pub impl_: HistoryImpl,
+}
+
+/// The values of the tracked cells after one transaction, as returned
+/// by [`History::at`].
+#[derive(Clone, Debug)]
+pub struct HistorySnapshot {
+    transaction_id: u64,
+    values: HistoryValues,
+}
+
+/// A tracked cell whose value differs between two transactions, as
+/// returned by [`History::diff`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistoryChange {
+    /// The name the cell is tracked under.
+    pub name: String,
+    /// The value in the earlier transaction, or `None` if the cell was
+    /// not tracked then.
+    pub before: Option<String>,
+    /// The value in the later transaction, or `None` if the cell was
+    /// no longer tracked.
+    pub after: Option<String>,
+}
+
+impl History {
+    /// Stop tracking the cell tracked under the given name.
+    /// Transactions already kept still hold its values.
+    pub fn untrack(&self, name: &str) {
+        self.impl_.untrack(name);
+    }
+
+    /// The ids of the transactions kept, oldest first.
+    pub fn transaction_ids(&self) -> Vec<u64> {
+        self.impl_.transaction_ids()
+    }
+
+    /// The values after the given transaction, or `None` if that
+    /// transaction is not kept.
+    pub fn at(&self, transaction_id: u64) -> Option<HistorySnapshot> {
+        self.impl_.at(transaction_id).map(|values| HistorySnapshot {
+            transaction_id,
+            values,
+        })
+    }
+
+    /// The values after the most recent transaction kept, if any.
+    pub fn latest(&self) -> Option<HistorySnapshot> {
+        let transaction_id = *self.transaction_ids().last()?;
+        self.at(transaction_id)
+    }
+
+    /// The tracked cells whose values after transaction `to` differ
+    /// from their values after transaction `from`, in name order, or
+    /// `None` if either transaction is not kept.
+    pub fn diff(&self, from: u64, to: u64) -> Option<Vec<HistoryChange>> {
+        let before = self.at(from)?;
+        let after = self.at(to)?;
+        Some(before.diff(&after))
+    }
+}
+
+impl HistorySnapshot {
+    /// The id of the transaction these values are from.
+    pub fn transaction_id(&self) -> u64 {
+        self.transaction_id
+    }
+
+    /// The value of the cell tracked under the given name, if any.
+    pub fn value_of(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    /// The values of all tracked cells, by name.
+    pub fn values(&self) -> &BTreeMap<String, String> {
+        &self.values
+    }
+
+    /// The tracked cells whose values in `other` differ from their
+    /// values here, in name order.
+    pub fn diff(&self, other: &HistorySnapshot) -> Vec<HistoryChange> {
+        let mut changes = Vec::new();
+        for (name, before) in self.values.iter() {
+            let after = other.values.get(name);
+            if after != Some(before) {
+                changes.push(HistoryChange {
+                    name: name.clone(),
+                    before: Some(before.clone()),
+                    after: after.cloned(),
+                });
+            }
+        }
+        for (name, after) in other.values.iter() {
+            if !self.values.contains_key(name) {
+                changes.push(HistoryChange {
+                    name: name.clone(),
+                    before: None,
+                    after: Some(after.clone()),
+                });
+            }
+        }
+        changes.sort_by(|a, b| a.name.cmp(&b.name));
+        changes
+    }
+}
@@ -18,6 +18,7 @@ use crate::impl_::stream::Stream;
 use crate::impl_::stream::StreamWeakForwardRef;
 use crate::impl_::stream::WeakStream;
 
+use std::collections::HashMap;
 use std::mem;
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -181,10 +182,9 @@ impl<A: Send + 'static> Cell<A> {
             // initial update of Cell incase of stream (in Cell::hold) firing during same transaction cell is created.
             {
                 let c = c.clone();
+                let sodium_ctx2 = sodium_ctx.clone();
                 sodium_ctx.pre_eot(move || {
-                    let mut update = node.data.update.write().unwrap();
-                    let update: &mut Box<_> = &mut *update;
-                    update();
+                    sodium_ctx2.run_update(&node);
                     // c captured, but not used so that update() will not crash here
                     c.nop();
                 });
@@ -453,6 +453,172 @@ impl<A: Send + 'static> Cell<A> {
         )
     }
 
+    // Lift over any number of inputs with a single node. `apply` copies
+    // any firing inputs into `state` and returns whether any fired.
+    pub fn lift_n<S, APPLY, FN>(
+        sodium_ctx: &SodiumCtx,
+        state: S,
+        dependencies: Vec<Box<dyn IsNode + Send + Sync>>,
+        deps: Vec<Dep>,
+        mut apply: APPLY,
+        f: FN,
+    ) -> Cell<A>
+    where
+        A: Clone,
+        S: Clone + Send + 'static,
+        APPLY: FnMut(&mut S) -> bool + Send + Sync + 'static,
+        FN: FnMut(&S) -> A + Send + 'static,
+    {
+        sodium_ctx.transaction(|| {
+            let f = Arc::new(Mutex::new(f));
+            let init: Lazy<A>;
+            {
+                let state = state.clone();
+                let f = f.clone();
+                init = Lazy::new(move || {
+                    let mut l = f.lock();
+                    let f = l.as_mut().unwrap();
+                    f(&state)
+                });
+            }
+            let state = Arc::new(Mutex::new(state));
+            Stream::_new(sodium_ctx, |s: StreamWeakForwardRef<A>| {
+                let node = Node::new(
+                    sodium_ctx,
+                    "Cell::lift_n",
+                    move || {
+                        let mut l = state.lock();
+                        let state: &mut S = l.as_mut().unwrap();
+                        if apply(state) {
+                            let mut l = f.lock();
+                            let f = l.as_mut().unwrap();
+                            s.unwrap()._send(f(state));
+                        }
+                    },
+                    dependencies,
+                );
+                <dyn IsNode>::add_update_dependencies(&node, deps);
+                node
+            })
+            .hold_lazy(init)
+        })
+    }
+
+    pub fn sequence(sodium_ctx: &SodiumCtx, cells: &[Cell<A>]) -> Cell<Vec<A>>
+    where
+        A: Clone,
+    {
+        let values: Vec<Lazy<A>> = cells.iter().map(Cell::sample_lazy).collect();
+        let init: Lazy<Vec<A>>;
+        {
+            let values = values.clone();
+            init = Lazy::new(move || values.iter().map(Lazy::run).collect());
+        }
+        let cells = cells.to_vec();
+        let fired: Fired<A> = Arc::new(Mutex::new(Vec::new()));
+        let state = Arc::new(Mutex::new(SequenceState {
+            inputs: HashMap::new(),
+            values,
+            current_op: None,
+        }));
+        Stream::_new(sodium_ctx, |s: StreamWeakForwardRef<Vec<A>>| {
+            let node;
+            {
+                let state = state.clone();
+                let fired = fired.clone();
+                node = Node::new(
+                    sodium_ctx,
+                    "Cell::sequence",
+                    move || {
+                        let mut l = state.lock();
+                        let state: &mut SequenceState<A> = l.as_mut().unwrap();
+                        if state.apply_fired(&fired) {
+                            s.unwrap()._send(state.current().clone());
+                        }
+                    },
+                    vec![],
+                );
+            }
+            let mut l = state.lock();
+            let state: &mut SequenceState<A> = l.as_mut().unwrap();
+            state.inputs = sequence_inputs(&cells, &node, &fired);
+            node
+        })
+        .hold_lazy(init)
+    }
+
+    pub fn switch_sequence(cca: &Cell<Vec<Cell<A>>>) -> Cell<Vec<A>>
+    where
+        A: Clone,
+    {
+        let sodium_ctx = cca.sodium_ctx();
+        let init: Lazy<Vec<A>>;
+        {
+            let cca = cca.clone();
+            init = Lazy::new(move || cca.sample().iter().map(Cell::sample).collect());
+        }
+        let fired: Fired<A> = Arc::new(Mutex::new(Vec::new()));
+        let state = Arc::new(Mutex::new(SequenceState {
+            inputs: HashMap::new(),
+            values: Vec::new(),
+            current_op: None,
+        }));
+        Stream::_new(&sodium_ctx, |s: StreamWeakForwardRef<Vec<A>>| {
+            let outer_updates = cca.updates();
+            let dependencies = vec![outer_updates.box_clone()];
+            let node;
+            {
+                let sodium_ctx2 = sodium_ctx.clone();
+                let state = state.clone();
+                let fired = fired.clone();
+                let outer_updates = outer_updates.clone();
+                let s = s.clone();
+                node = Node::new(
+                    &sodium_ctx,
+                    "Cell::switch_sequence",
+                    move || {
+                        let mut l = state.lock();
+                        let state: &mut SequenceState<A> = l.as_mut().unwrap();
+                        let s = s.unwrap();
+                        let membership_changed =
+                            outer_updates.with_firing_op(|firing_op: &mut Option<Vec<Cell<A>>>| {
+                                match firing_op {
+                                    Some(ref cells) => {
+                                        state.set_cells(&sodium_ctx2, cells, s.node(), &fired);
+                                        true
+                                    }
+                                    None => false,
+                                }
+                            });
+                        if state.apply_fired(&fired) || membership_changed {
+                            s._send(state.current().clone());
+                        }
+                    },
+                    dependencies,
+                );
+            }
+            <dyn IsNode>::add_update_dependencies(&node, vec![outer_updates.to_dep()]);
+            // The initial cells are attached at the end of the
+            // transaction, in case `cca` is a `CellLoop`.
+            {
+                let cca = cca.clone();
+                let node = node.clone();
+                let state = state.clone();
+                sodium_ctx.pre_eot(move || {
+                    let cells = cca.sample();
+                    let mut l = state.lock();
+                    let state: &mut SequenceState<A> = l.as_mut().unwrap();
+                    if state.inputs.is_empty() && state.current_op.is_none() {
+                        state.values = cells.iter().map(Cell::sample_lazy).collect();
+                        state.inputs = sequence_inputs(&cells, &node, &fired);
+                    }
+                });
+            }
+            node
+        })
+        .hold_lazy(init)
+    }
+
     pub fn switch_s(csa: &Cell<Stream<A>>) -> Stream<A>
     where
         A: Clone,
@@ -683,3 +849,133 @@ impl<A> WeakCell<A> {
         Some(Cell { data, node })
     }
 }
+
+// The firings of the input cells of a `sequence`, tagged with the id
+// of each cell's update stream, so that an update only visits the
+// inputs that fired.
+type Fired<A> = Arc<Mutex<Vec<(u32, A)>>>;
+
+struct SequenceInput {
+    tagger: WeakStream<()>,
+    // The positions of the cell in the output.
+    positions: Vec<usize>,
+}
+
+struct SequenceState<A> {
+    inputs: HashMap<u32, SequenceInput>,
+    // The values stay lazy until the first update, after which only
+    // the changed elements of `current_op` are replaced.
+    values: Vec<Lazy<A>>,
+    current_op: Option<Vec<A>>,
+}
+
+impl<A: Send + Clone + 'static> SequenceState<A> {
+    fn current(&mut self) -> &mut Vec<A> {
+        if self.current_op.is_none() {
+            let values = mem::take(&mut self.values);
+            self.current_op = Some(values.iter().map(Lazy::run).collect());
+        }
+        self.current_op.as_mut().unwrap()
+    }
+
+    // Copy the values of the inputs that fired into the output, and
+    // return whether any did.
+    fn apply_fired(&mut self, fired: &Fired<A>) -> bool {
+        let fired = mem::take(&mut *fired.lock().unwrap());
+        if fired.is_empty() {
+            return false;
+        }
+        self.current();
+        let current = self.current_op.as_mut().unwrap();
+        for (id, a) in fired {
+            if let Some(input) = self.inputs.get(&id) {
+                for &position in &input.positions {
+                    current[position] = a.clone();
+                }
+            }
+        }
+        true
+    }
+
+    // Replace the input cells, keeping the taggers of those that stay
+    // and detaching those of the ones that go.
+    fn set_cells(
+        &mut self,
+        sodium_ctx: &SodiumCtx,
+        cells: &[Cell<A>],
+        node: &Node,
+        fired: &Fired<A>,
+    ) {
+        self.current();
+        let old_current = self.current_op.take().unwrap();
+        let mut old_inputs = mem::take(&mut self.inputs);
+        let mut current: Vec<A> = Vec::with_capacity(cells.len());
+        for (position, cell) in cells.iter().enumerate() {
+            let updates = cell.updates();
+            let id = updates.node().gc_node.id();
+            if let Some(input) = self.inputs.get_mut(&id) {
+                current.push(current[input.positions[0]].clone());
+                input.positions.push(position);
+            } else if let Some(mut input) = old_inputs.remove(&id) {
+                current.push(old_current[input.positions[0]].clone());
+                input.positions = vec![position];
+                self.inputs.insert(id, input);
+            } else {
+                // A new cell that fires in this transaction is seen
+                // here rather than through its tagger.
+                sodium_ctx.update_node(updates.node());
+                let value = updates
+                    .with_firing_op(|firing_op: &mut Option<A>| firing_op.clone())
+                    .unwrap_or_else(|| cell.sample());
+                current.push(value);
+                self.inputs
+                    .insert(id, sequence_input(cell, position, node, fired));
+            }
+        }
+        for (_, input) in old_inputs {
+            if let Some(tagger) = input.tagger.upgrade() {
+                <dyn IsNode>::remove_dependency(node, &tagger);
+            }
+        }
+        self.current_op = Some(current);
+    }
+}
+
+fn sequence_inputs<A: Send + Clone + 'static>(
+    cells: &[Cell<A>],
+    node: &Node,
+    fired: &Fired<A>,
+) -> HashMap<u32, SequenceInput> {
+    let mut inputs: HashMap<u32, SequenceInput> = HashMap::new();
+    for (position, cell) in cells.iter().enumerate() {
+        let id = cell.updates().node().gc_node.id();
+        match inputs.get_mut(&id) {
+            Some(input) => input.positions.push(position),
+            None => {
+                inputs.insert(id, sequence_input(cell, position, node, fired));
+            }
+        }
+    }
+    inputs
+}
+
+// Attach a tagger for the cell as a dependency of the sequence node.
+fn sequence_input<A: Send + Clone + 'static>(
+    cell: &Cell<A>,
+    position: usize,
+    node: &Node,
+    fired: &Fired<A>,
+) -> SequenceInput {
+    let updates = cell.updates();
+    let id = updates.node().gc_node.id();
+    let fired = fired.clone();
+    let tagger = updates.map(move |a: &A| {
+        let mut l = fired.lock();
+        l.as_mut().unwrap().push((id, a.clone()));
+    });
+    <dyn IsNode>::add_dependency(node, tagger.clone());
+    SequenceInput {
+        tagger: Stream::downgrade(&tagger),
+        positions: vec![position],
+    }
+}
@@ -1,12 +1,19 @@
-use crate::impl_::cell::Cell;
+use crate::impl_::cell::{Cell, WeakCell};
 use crate::impl_::sodium_ctx::SodiumCtx;
-use crate::impl_::stream_sink::StreamSink;
+#[cfg(feature = "recording")]
+use crate::impl_::stream_sink::RecordingHook;
+use crate::impl_::stream_sink::{StreamSink, WeakStreamSink};
 
 pub struct CellSink<A> {
     cell: Cell<A>,
     stream_sink: StreamSink<A>,
 }
 
+pub struct WeakCellSink<A> {
+    cell: WeakCell<A>,
+    stream_sink: WeakStreamSink<A>,
+}
+
 impl<A> Clone for CellSink<A> {
     fn clone(&self) -> Self {
         CellSink {
@@ -16,6 +23,15 @@ impl<A> Clone for CellSink<A> {
     }
 }
 
+impl<A> Clone for WeakCellSink<A> {
+    fn clone(&self) -> Self {
+        WeakCellSink {
+            cell: self.cell.clone(),
+            stream_sink: self.stream_sink.clone(),
+        }
+    }
+}
+
 impl<A: Send + Clone + 'static> CellSink<A> {
     pub fn new(sodium_ctx: &SodiumCtx, a: A) -> CellSink<A> {
         let stream_sink = StreamSink::new(sodium_ctx);
@@ -29,7 +45,27 @@ impl<A: Send + Clone + 'static> CellSink<A> {
         self.cell.clone()
     }
 
+    #[cfg(feature = "recording")]
+    pub fn recording_hook(&self) -> RecordingHook<A> {
+        self.stream_sink.recording_hook()
+    }
+
     pub fn send(&self, a: A) {
         self.stream_sink.send(a);
     }
+
+    pub fn downgrade(this: &Self) -> WeakCellSink<A> {
+        WeakCellSink {
+            cell: Cell::downgrade(&this.cell),
+            stream_sink: StreamSink::downgrade(&this.stream_sink),
+        }
+    }
+}
+
+impl<A> WeakCellSink<A> {
+    pub fn upgrade(&self) -> Option<CellSink<A>> {
+        let cell = self.cell.upgrade()?;
+        let stream_sink = self.stream_sink.upgrade()?;
+        Some(CellSink { cell, stream_sink })
+    }
 }
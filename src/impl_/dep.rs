@@ -1,5 +1,9 @@
 use crate::impl_::gc_node::GcNode;
 
+use std::collections::HashMap;
+
+/// A reference to a node in the FRP graph that is captured by a
+/// lambda, so that the garbage collector can see it.
 #[derive(Clone)]
 pub struct Dep {
     gc_node: GcNode,
@@ -14,3 +18,82 @@ impl Dep {
         &self.gc_node
     }
 }
+
+/// Interface for values that hold references to Sodium objects.
+///
+/// Any value implementing `Trace` can be passed as the dependencies
+/// of [`lambda1`][crate::lambda1] through
+/// [`lambda6`][crate::lambda6]. Implement it for your own types that
+/// hold [`Stream`][crate::Stream]s or [`Cell`][crate::Cell]s by
+/// calling `trace` on each of the fields that refer to Sodium
+/// objects.
+///
+/// A [`Lazy`][crate::Lazy] is only traced once its value has been
+/// computed. The objects captured by a thunk that has not run yet
+/// cannot be seen, so they must be traced separately.
+pub trait Trace {
+    /// Push a [`Dep`] for every Sodium object referenced by this
+    /// value onto `deps`.
+    fn trace(&self, deps: &mut Vec<Dep>);
+
+    /// Collect the [`Dep`]s for every Sodium object referenced by
+    /// this value.
+    fn to_deps(&self) -> Vec<Dep> {
+        let mut deps = Vec::new();
+        self.trace(&mut deps);
+        deps
+    }
+}
+
+impl Trace for Dep {
+    fn trace(&self, deps: &mut Vec<Dep>) {
+        deps.push(self.clone());
+    }
+}
+
+impl Trace for () {
+    fn trace(&self, _deps: &mut Vec<Dep>) {}
+}
+
+impl<T: Trace> Trace for Vec<T> {
+    fn trace(&self, deps: &mut Vec<Dep>) {
+        for x in self {
+            x.trace(deps);
+        }
+    }
+}
+
+impl<T: Trace> Trace for Option<T> {
+    fn trace(&self, deps: &mut Vec<Dep>) {
+        if let Some(x) = self {
+            x.trace(deps);
+        }
+    }
+}
+
+impl<K, V: Trace, S> Trace for HashMap<K, V, S> {
+    fn trace(&self, deps: &mut Vec<Dep>) {
+        for v in self.values() {
+            v.trace(deps);
+        }
+    }
+}
+
+macro_rules! impl_trace_for_tuple {
+    ($($t:ident $i:tt),+) => {
+        impl<$($t: Trace),+> Trace for ($($t,)+) {
+            fn trace(&self, deps: &mut Vec<Dep>) {
+                $(self.$i.trace(deps);)+
+            }
+        }
+    };
+}
+
+impl_trace_for_tuple!(A 0);
+impl_trace_for_tuple!(A 0, B 1);
+impl_trace_for_tuple!(A 0, B 1, C 2);
+impl_trace_for_tuple!(A 0, B 1, C 2, D 3);
+impl_trace_for_tuple!(A 0, B 1, C 2, D 3, E 4);
+impl_trace_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5);
+impl_trace_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_trace_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
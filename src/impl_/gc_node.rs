@@ -80,6 +80,11 @@ impl GcCtx {
         })
     }
 
+    // the id that will be given to the next gc node created
+    pub fn next_id(&self) -> u32 {
+        self.with_data(|data: &mut GcCtxData| data.next_id)
+    }
+
     pub fn add_possible_root(&self, node: GcNode) {
         self.with_data(|data: &mut GcCtxData| data.roots.push(node));
     }
@@ -332,6 +337,14 @@ impl GcNode {
         }
     }
 
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn ref_count(&self) -> u32 {
         self.data.ref_count.get()
     }
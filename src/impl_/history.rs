@@ -0,0 +1,103 @@
+use crate::impl_::listener::Listener;
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+pub type HistoryValues = Arc<BTreeMap<String, String>>;
+
+#[derive(Clone, Default)]
+pub struct History {
+    // Whether the capacity is above 0, so that the end of each
+    // transaction can skip the lock while history is disabled.
+    enabled: Arc<AtomicBool>,
+    data: Arc<Mutex<HistoryData>>,
+}
+
+#[derive(Default)]
+struct HistoryData {
+    // The number of transactions kept, or 0 if history is disabled.
+    capacity: usize,
+    // The latest value of every tracked cell. This is shared with the
+    // most recent entry, and copied on write once a tracked cell
+    // changes.
+    current: HistoryValues,
+    entries: VecDeque<(u64, HistoryValues)>,
+    listeners: HashMap<String, Listener>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History::default()
+    }
+
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut data = self.data.lock().unwrap();
+        data.capacity = capacity;
+        self.enabled.store(capacity > 0, Ordering::Relaxed);
+        while data.entries.len() > capacity {
+            data.entries.pop_front();
+        }
+    }
+
+    // Replaces the listener feeding any cell already tracked under
+    // the name.
+    pub fn track(&self, name: &str, listener: Listener) {
+        let old_listener = {
+            let mut data = self.data.lock().unwrap();
+            data.listeners.insert(name.to_string(), listener)
+        };
+        if let Some(old_listener) = old_listener {
+            old_listener.unlisten();
+        }
+    }
+
+    pub fn untrack(&self, name: &str) {
+        let listener = {
+            let mut data = self.data.lock().unwrap();
+            Arc::make_mut(&mut data.current).remove(name);
+            data.listeners.remove(name)
+        };
+        if let Some(listener) = listener {
+            listener.unlisten();
+        }
+    }
+
+    pub fn record(&self, name: &str, value: String) {
+        let mut data = self.data.lock().unwrap();
+        Arc::make_mut(&mut data.current).insert(name.to_string(), value);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn end_of_transaction(&self, transaction_id: u64) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut data = self.data.lock().unwrap();
+        if data.capacity == 0 {
+            return;
+        }
+        let values = data.current.clone();
+        data.entries.push_back((transaction_id, values));
+        while data.entries.len() > data.capacity {
+            data.entries.pop_front();
+        }
+    }
+
+    pub fn transaction_ids(&self) -> Vec<u64> {
+        let data = self.data.lock().unwrap();
+        data.entries.iter().map(|(id, _)| *id).collect()
+    }
+
+    pub fn at(&self, transaction_id: u64) -> Option<HistoryValues> {
+        let data = self.data.lock().unwrap();
+        let index = data
+            .entries
+            .binary_search_by_key(&transaction_id, |(id, _)| *id)
+            .ok()?;
+        Some(data.entries[index].1.clone())
+    }
+}
@@ -0,0 +1,267 @@
+use crate::list_tree::next_priority;
+use std::cmp::Ordering;
+use std::mem;
+use std::ops::Bound;
+
+// A map from ranges of keys to values that can find every range
+// containing a given key without visiting the others. It is a treap
+// ordered by the ranges' bounds, where each node also records the
+// greatest end bound in its subtree, so a lookup skips any subtree
+// whose ranges all end before the key.
+pub struct IntervalIndex<K, V> {
+    root: Link<K, V>,
+}
+
+type Link<K, V> = Option<Box<IntervalNode<K, V>>>;
+
+struct IntervalNode<K, V> {
+    start: Start<K>,
+    end: End<K>,
+    value: V,
+    priority: u64,
+    max_end: End<K>,
+    left: Link<K, V>,
+    right: Link<K, V>,
+}
+
+// The start of a range, ordered so that the ranges that can contain a
+// key `k` are exactly those that start at or before `Included(k)`.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Start<K> {
+    Unbounded,
+    Included(K),
+    Excluded(K),
+}
+
+impl<K: Ord> Start<K> {
+    fn allows(&self, k: &K) -> bool {
+        match self {
+            Start::Unbounded => true,
+            Start::Included(start) => start <= k,
+            Start::Excluded(start) => start < k,
+        }
+    }
+}
+
+impl<K: Ord> Ord for Start<K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Start::Unbounded, Start::Unbounded) => Ordering::Equal,
+            (Start::Unbounded, _) => Ordering::Less,
+            (_, Start::Unbounded) => Ordering::Greater,
+            (Start::Included(a), Start::Included(b)) | (Start::Excluded(a), Start::Excluded(b)) => {
+                a.cmp(b)
+            }
+            (Start::Included(a), Start::Excluded(b)) => a.cmp(b).then(Ordering::Less),
+            (Start::Excluded(a), Start::Included(b)) => a.cmp(b).then(Ordering::Greater),
+        }
+    }
+}
+
+impl<K: Ord> PartialOrd for Start<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Clone> Start<K> {
+    pub fn from_bound(bound: Bound<&K>) -> Start<K> {
+        match bound {
+            Bound::Unbounded => Start::Unbounded,
+            Bound::Included(k) => Start::Included(k.clone()),
+            Bound::Excluded(k) => Start::Excluded(k.clone()),
+        }
+    }
+}
+
+// The end of a range, ordered so that a range ending later is greater.
+#[derive(Clone, PartialEq, Eq)]
+pub enum End<K> {
+    Excluded(K),
+    Included(K),
+    Unbounded,
+}
+
+impl<K: Ord> End<K> {
+    fn allows(&self, k: &K) -> bool {
+        match self {
+            End::Unbounded => true,
+            End::Included(end) => k <= end,
+            End::Excluded(end) => k < end,
+        }
+    }
+}
+
+impl<K: Ord> Ord for End<K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (End::Unbounded, End::Unbounded) => Ordering::Equal,
+            (End::Unbounded, _) => Ordering::Greater,
+            (_, End::Unbounded) => Ordering::Less,
+            (End::Included(a), End::Included(b)) | (End::Excluded(a), End::Excluded(b)) => a.cmp(b),
+            (End::Included(a), End::Excluded(b)) => a.cmp(b).then(Ordering::Greater),
+            (End::Excluded(a), End::Included(b)) => a.cmp(b).then(Ordering::Less),
+        }
+    }
+}
+
+impl<K: Ord> PartialOrd for End<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Clone> End<K> {
+    pub fn from_bound(bound: Bound<&K>) -> End<K> {
+        match bound {
+            Bound::Unbounded => End::Unbounded,
+            Bound::Included(k) => End::Included(k.clone()),
+            Bound::Excluded(k) => End::Excluded(k.clone()),
+        }
+    }
+}
+
+impl<K: Clone + Ord, V> IntervalNode<K, V> {
+    fn cmp_range(&self, start: &Start<K>, end: &End<K>) -> Ordering {
+        (&self.start, &self.end).cmp(&(start, end))
+    }
+
+    fn update(&mut self) {
+        let mut max_end = &self.end;
+        for child in [&self.left, &self.right].iter().copied().flatten() {
+            if child.max_end > *max_end {
+                max_end = &child.max_end;
+            }
+        }
+        self.max_end = max_end.clone();
+    }
+}
+
+// Split into the ranges ordered before the given one, and the rest.
+fn split<K: Clone + Ord, V>(
+    link: Link<K, V>,
+    start: &Start<K>,
+    end: &End<K>,
+) -> (Link<K, V>, Link<K, V>) {
+    match link {
+        None => (None, None),
+        Some(mut node) => {
+            if node.cmp_range(start, end) == Ordering::Less {
+                let (l, r) = split(node.right.take(), start, end);
+                node.right = l;
+                node.update();
+                (Some(node), r)
+            } else {
+                let (l, r) = split(node.left.take(), start, end);
+                node.left = r;
+                node.update();
+                (l, Some(node))
+            }
+        }
+    }
+}
+
+fn merge<K: Clone + Ord, V>(a: Link<K, V>, b: Link<K, V>) -> Link<K, V> {
+    match (a, b) {
+        (None, b) => b,
+        (a, None) => a,
+        (Some(mut a), Some(mut b)) => {
+            if a.priority > b.priority {
+                a.right = merge(a.right.take(), Some(b));
+                a.update();
+                Some(a)
+            } else {
+                b.left = merge(Some(a), b.left.take());
+                b.update();
+                Some(b)
+            }
+        }
+    }
+}
+
+fn remove<K: Clone + Ord, V>(link: &mut Link<K, V>, start: &Start<K>, end: &End<K>) -> Option<V> {
+    let ordering = link.as_ref()?.cmp_range(start, end);
+    if ordering == Ordering::Equal {
+        let mut node = link.take().unwrap();
+        *link = merge(node.left.take(), node.right.take());
+        return Some(node.value);
+    }
+    let node = link.as_mut().unwrap();
+    let result = if ordering == Ordering::Less {
+        remove(&mut node.right, start, end)
+    } else {
+        remove(&mut node.left, start, end)
+    };
+    node.update();
+    result
+}
+
+fn for_each_containing<K: Clone + Ord, V>(link: &Link<K, V>, k: &K, f: &mut impl FnMut(&V)) {
+    let node = match link {
+        Some(node) => node,
+        None => return,
+    };
+    if !node.max_end.allows(k) {
+        return;
+    }
+    for_each_containing(&node.left, k, f);
+    // The ranges to the right start no earlier than this one.
+    if !node.start.allows(k) {
+        return;
+    }
+    if node.end.allows(k) {
+        f(&node.value);
+    }
+    for_each_containing(&node.right, k, f);
+}
+
+impl<K: Clone + Ord, V> IntervalIndex<K, V> {
+    pub fn new() -> IntervalIndex<K, V> {
+        IntervalIndex { root: None }
+    }
+
+    pub fn get(&self, start: &Start<K>, end: &End<K>) -> Option<&V> {
+        let mut link = &self.root;
+        while let Some(node) = link {
+            match node.cmp_range(start, end) {
+                Ordering::Less => link = &node.right,
+                Ordering::Greater => link = &node.left,
+                Ordering::Equal => return Some(&node.value),
+            }
+        }
+        None
+    }
+
+    // Insert a value for the range, returning the value it replaces.
+    pub fn insert(&mut self, start: Start<K>, end: End<K>, value: V) -> Option<V> {
+        let mut link = &mut self.root;
+        while let Some(node) = link {
+            match node.cmp_range(&start, &end) {
+                Ordering::Less => link = &mut node.right,
+                Ordering::Greater => link = &mut node.left,
+                Ordering::Equal => return Some(mem::replace(&mut node.value, value)),
+            }
+        }
+        let (l, r) = split(self.root.take(), &start, &end);
+        let node = IntervalNode {
+            max_end: end.clone(),
+            start,
+            end,
+            value,
+            priority: next_priority(),
+            left: None,
+            right: None,
+        };
+        self.root = merge(merge(l, Some(Box::new(node))), r);
+        None
+    }
+
+    pub fn remove(&mut self, start: &Start<K>, end: &End<K>) -> Option<V> {
+        remove(&mut self.root, start, end)
+    }
+
+    // Call `f` with the value of each range that contains `k`.
+    pub fn for_each_containing(&self, k: &K, mut f: impl FnMut(&V)) {
+        for_each_containing(&self.root, k, &mut f);
+    }
+}
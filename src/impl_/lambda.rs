@@ -1,6 +1,6 @@
 #![allow(clippy::many_single_char_names)]
 
-use crate::impl_::dep::Dep;
+use crate::impl_::dep::{Dep, Trace};
 
 pub struct Lambda<FN> {
     f: FN,
@@ -193,32 +193,121 @@ impl<A, B, C, D, E, F, G, FN: FnMut(&A, &B, &C, &D, &E, &F) -> G> IsLambda6<A, B
     }
 }
 
-pub fn lambda1<A, B, FN: FnMut(&A) -> B>(f: FN, deps: Vec<Dep>) -> Lambda<FN> {
-    Lambda { f, deps }
+/// Create a lambda function of one argument that declares the Sodium objects
+/// in `deps` as its dependencies.
+pub fn lambda1<A, B, FN: FnMut(&A) -> B, DEPS: Trace>(f: FN, deps: DEPS) -> Lambda<FN> {
+    Lambda {
+        f,
+        deps: deps.to_deps(),
+    }
 }
 
-pub fn lambda2<A, B, C, FN: FnMut(&A, &B) -> C>(f: FN, deps: Vec<Dep>) -> Lambda<FN> {
-    Lambda { f, deps }
+/// Create a lambda function of two arguments that declares the Sodium objects
+/// in `deps` as its dependencies.
+pub fn lambda2<A, B, C, FN: FnMut(&A, &B) -> C, DEPS: Trace>(f: FN, deps: DEPS) -> Lambda<FN> {
+    Lambda {
+        f,
+        deps: deps.to_deps(),
+    }
 }
 
-pub fn lambda3<A, B, C, D, FN: FnMut(&A, &B, &C) -> D>(f: FN, deps: Vec<Dep>) -> Lambda<FN> {
-    Lambda { f, deps }
+/// Create a lambda function of three arguments that declares the Sodium objects
+/// in `deps` as its dependencies.
+pub fn lambda3<A, B, C, D, FN: FnMut(&A, &B, &C) -> D, DEPS: Trace>(
+    f: FN,
+    deps: DEPS,
+) -> Lambda<FN> {
+    Lambda {
+        f,
+        deps: deps.to_deps(),
+    }
 }
 
-pub fn lambda4<A, B, C, D, E, FN: FnMut(&A, &B, &C, &D) -> E>(f: FN, deps: Vec<Dep>) -> Lambda<FN> {
-    Lambda { f, deps }
+/// Create a lambda function of four arguments that declares the Sodium objects
+/// in `deps` as its dependencies.
+pub fn lambda4<A, B, C, D, E, FN: FnMut(&A, &B, &C, &D) -> E, DEPS: Trace>(
+    f: FN,
+    deps: DEPS,
+) -> Lambda<FN> {
+    Lambda {
+        f,
+        deps: deps.to_deps(),
+    }
 }
 
-pub fn lambda5<A, B, C, D, E, F, FN: FnMut(&A, &B, &C, &D, &E) -> F>(
+/// Create a lambda function of five arguments that declares the Sodium objects
+/// in `deps` as its dependencies.
+pub fn lambda5<A, B, C, D, E, F, FN: FnMut(&A, &B, &C, &D, &E) -> F, DEPS: Trace>(
     f: FN,
-    deps: Vec<Dep>,
+    deps: DEPS,
 ) -> Lambda<FN> {
-    Lambda { f, deps }
+    Lambda {
+        f,
+        deps: deps.to_deps(),
+    }
 }
 
-pub fn lambda6<A, B, C, D, E, F, G, FN: FnMut(&A, &B, &C, &D, &E, &F) -> G>(
+/// Create a lambda function of six arguments that declares the Sodium objects
+/// in `deps` as its dependencies.
+pub fn lambda6<A, B, C, D, E, F, G, FN: FnMut(&A, &B, &C, &D, &E, &F) -> G, DEPS: Trace>(
     f: FN,
-    deps: Vec<Dep>,
+    deps: DEPS,
 ) -> Lambda<FN> {
-    Lambda { f, deps }
+    Lambda {
+        f,
+        deps: deps.to_deps(),
+    }
+}
+
+/// Create a lambda whose dependencies are the listed Sodium objects.
+///
+/// Each of the listed handles is cloned into the closure, and the
+/// clones are passed as the dependencies of the `lambda1` through
+/// `lambda6` function matching the number of closure parameters. The
+/// handles can be anything implementing [`Trace`][crate::Trace].
+///
+/// For example `lambda!([ca, sb] move |x: &i32| x + ca.sample())`
+/// expands to `lambda1` with `ca` and `sb` as its dependencies.
+#[macro_export]
+macro_rules! lambda {
+    ([$($dep:ident),* $(,)?] $(move)? |$($arg:tt : $ty:ty),*| -> $ret:ty { $($body:tt)* }) => {{
+        $(let $dep = $dep.clone();)*
+        let deps = {
+            #[allow(unused_mut)]
+            let mut deps: Vec<$crate::Dep> = Vec::new();
+            $($crate::Trace::trace(&$dep, &mut deps);)*
+            deps
+        };
+        let f = move |$($arg: $ty),*| -> $ret { $($body)* };
+        $crate::lambda!(@arity deps ($($ty),*) f)
+    }};
+    ([$($dep:ident),* $(,)?] $(move)? |$($arg:tt : $ty:ty),*| $body:expr) => {{
+        $(let $dep = $dep.clone();)*
+        let deps = {
+            #[allow(unused_mut)]
+            let mut deps: Vec<$crate::Dep> = Vec::new();
+            $($crate::Trace::trace(&$dep, &mut deps);)*
+            deps
+        };
+        let f = move |$($arg: $ty),*| $body;
+        $crate::lambda!(@arity deps ($($ty),*) f)
+    }};
+    (@arity $deps:ident ($a:ty) $f:ident) => {
+        $crate::lambda1($f, $deps)
+    };
+    (@arity $deps:ident ($a:ty, $b:ty) $f:ident) => {
+        $crate::lambda2($f, $deps)
+    };
+    (@arity $deps:ident ($a:ty, $b:ty, $c:ty) $f:ident) => {
+        $crate::lambda3($f, $deps)
+    };
+    (@arity $deps:ident ($a:ty, $b:ty, $c:ty, $d:ty) $f:ident) => {
+        $crate::lambda4($f, $deps)
+    };
+    (@arity $deps:ident ($a:ty, $b:ty, $c:ty, $d:ty, $e:ty) $f:ident) => {
+        $crate::lambda5($f, $deps)
+    };
+    (@arity $deps:ident ($a:ty, $b:ty, $c:ty, $d:ty, $e:ty, $f2:ty) $f:ident) => {
+        $crate::lambda6($f, $deps)
+    };
 }
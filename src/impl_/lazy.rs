@@ -1,3 +1,5 @@
+use crate::impl_::dep::{Dep, Trace};
+
 use std::sync::Arc;
 use std::sync::Mutex;
 
@@ -15,6 +17,22 @@ impl<A> Clone for Lazy<A> {
     }
 }
 
+/// Only a value that has already been computed is traced. Tracing a
+/// `Lazy` whose thunk has not run yet declares no dependencies, since
+/// running the thunk here could sample a `CellLoop` before it is
+/// looped, so a `Lazy` holding Sodium objects should be
+/// [`run`][Lazy::run] before it is captured if they are to be
+/// declared.
+impl<A: Trace> Trace for Lazy<A> {
+    fn trace(&self, deps: &mut Vec<Dep>) {
+        let l = self.data.lock();
+        let data: &LazyData<A> = l.as_ref().unwrap();
+        if let LazyData::Value(ref x) = data {
+            x.trace(deps);
+        }
+    }
+}
+
 pub enum LazyData<A> {
     Thunk(Box<dyn FnMut() -> A + Send>),
     Value(A),
@@ -4,6 +4,7 @@ use crate::impl_::sodium_ctx::SodiumCtx;
 use crate::impl_::sodium_ctx::SodiumCtxData;
 
 use std::fmt;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Mutex;
 
@@ -36,6 +37,7 @@ pub struct ListenerData {
 
 impl Listener {
     pub fn new(sodium_ctx: &SodiumCtx, is_weak: bool, node: Node) -> Listener {
+        node.data.is_listener.store(true, Ordering::Relaxed);
         let listener_data = Arc::new(Mutex::new(ListenerData {
             sodium_ctx: sodium_ctx.clone(),
             node_op: Some(node),
@@ -5,13 +5,17 @@ pub mod cell_loop;
 pub mod cell_sink;
 pub mod dep;
 pub mod gc_node;
+pub mod history;
+pub mod interval_index;
 pub mod lambda;
 pub mod lazy;
 pub mod listener;
 pub mod node;
 pub mod router;
 pub mod sodium_ctx;
+pub mod state_store;
 pub mod stream;
 pub mod stream_loop;
 pub mod stream_sink;
+pub mod these;
 pub mod transaction;
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fmt;
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use std::sync::RwLock;
 use std::sync::Weak;
@@ -150,6 +151,9 @@ pub struct NodeData {
     pub dependents: RwLock<Vec<Box<dyn IsWeakNode + Send + Sync>>>,
     pub keep_alive: RwLock<Vec<GcNode>>,
     pub cleanups: RwLock<Vec<Box<dyn FnMut() + Send + Sync>>>,
+    // Set for the nodes of listeners, whose callbacks may sample any
+    // cell.
+    pub is_listener: AtomicBool,
     pub sodium_ctx: SodiumCtx,
 }
 
@@ -311,6 +315,7 @@ impl Node {
                 dependents: RwLock::new(Vec::new()),
                 keep_alive: RwLock::new(Vec::new()),
                 cleanups: RwLock::new(Vec::new()),
+                is_listener: AtomicBool::new(false),
                 sodium_ctx: sodium_ctx.clone(),
             }),
             gc_node: GcNode::new(&sodium_ctx.gc_ctx(), name.to_string(), deconstructor, trace),
@@ -1,19 +1,38 @@
+use crate::impl_::interval_index::{End, IntervalIndex, Start};
 use crate::impl_::node::{IsNode, IsWeakNode, Node, WeakNode};
 use crate::impl_::sodium_ctx::{SodiumCtx, SodiumCtxData};
 use crate::impl_::stream::{Stream, WeakStream};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::hash::Hash;
+use std::ops::{Bound, RangeBounds};
 use std::sync::{Arc, RwLock};
 
+/// A change to the set of keys of a [`Router`][crate::Router] that
+/// have a live [`filter_matches`][crate::Router::filter_matches]
+/// stream.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum RouterChange<K> {
+    /// The key gained its first subscriber.
+    Added(K),
+    /// The key lost its last subscriber.
+    Removed(K),
+}
+
+type WeakStreamSlot<A> = Arc<RwLock<Option<WeakStream<A>>>>;
+
 pub struct Router<A, K> {
     sodium_ctx: SodiumCtx,
     table: Arc<RwLock<HashMap<K, WeakStream<A>>>>,
+    unmatched: WeakStreamSlot<A>,
+    changes: WeakStreamSlot<RouterChange<K>>,
     node: Node,
 }
 
 pub struct WeakRouter<A, K> {
     sodium_ctx: SodiumCtx,
     table: Arc<RwLock<HashMap<K, WeakStream<A>>>>,
+    unmatched: WeakStreamSlot<A>,
+    changes: WeakStreamSlot<RouterChange<K>>,
     node: WeakNode,
 }
 
@@ -22,6 +41,8 @@ impl<A, K> Clone for Router<A, K> {
         Router {
             sodium_ctx: self.sodium_ctx.clone(),
             table: self.table.clone(),
+            unmatched: self.unmatched.clone(),
+            changes: self.changes.clone(),
             node: self.node.clone(),
         }
     }
@@ -32,6 +53,8 @@ impl<A, K> Clone for WeakRouter<A, K> {
         WeakRouter {
             sodium_ctx: self.sodium_ctx.clone(),
             table: self.table.clone(),
+            unmatched: self.unmatched.clone(),
+            changes: self.changes.clone(),
             node: self.node.clone(),
         }
     }
@@ -50,6 +73,8 @@ impl<A: Send + 'static, K: Send + Sync + 'static> IsNode for Router<A, K> {
         Box::new(WeakRouter {
             sodium_ctx: self.sodium_ctx.clone(),
             table: self.table.clone(),
+            unmatched: self.unmatched.clone(),
+            changes: self.changes.clone(),
             node: Node::downgrade2(&self.node),
         })
     }
@@ -69,6 +94,8 @@ impl<A: Send + 'static, K: Send + Sync + 'static> IsWeakNode for WeakRouter<A, K
             Some(Box::new(Router {
                 sodium_ctx: self.sodium_ctx.clone(),
                 table: self.table.clone(),
+                unmatched: self.unmatched.clone(),
+                changes: self.changes.clone(),
                 node,
             }))
         } else {
@@ -83,74 +110,137 @@ impl<A, K> Router<A, K> {
         in_stream: &Stream<A>,
         selector: impl Fn(&A) -> Vec<K> + Send + Sync + 'static,
     ) -> Router<A, K>
+    where
+        A: Clone + Send + 'static,
+        K: Send + Sync + Eq + Hash + 'static,
+    {
+        Router::_new(
+            sodium_ctx,
+            in_stream,
+            move |a: &A, route: &mut dyn FnMut(K)| {
+                for key in selector(a) {
+                    route(key);
+                }
+            },
+        )
+    }
+
+    pub fn new_single(
+        sodium_ctx: &SodiumCtx,
+        in_stream: &Stream<A>,
+        selector: impl Fn(&A) -> Option<K> + Send + Sync + 'static,
+    ) -> Router<A, K>
+    where
+        A: Clone + Send + 'static,
+        K: Send + Sync + Eq + Hash + 'static,
+    {
+        Router::_new(
+            sodium_ctx,
+            in_stream,
+            move |a: &A, route: &mut dyn FnMut(K)| {
+                if let Some(key) = selector(a) {
+                    route(key);
+                }
+            },
+        )
+    }
+
+    // `select` passes each key of an event to `route`, so that no
+    // collection of the keys needs to be built.
+    fn _new(
+        sodium_ctx: &SodiumCtx,
+        in_stream: &Stream<A>,
+        select: impl Fn(&A, &mut dyn FnMut(K)) + Send + Sync + 'static,
+    ) -> Router<A, K>
     where
         A: Clone + Send + 'static,
         K: Send + Sync + Eq + Hash + 'static,
     {
         let node;
         let table = Arc::new(RwLock::new(HashMap::<K, WeakStream<A>>::new()));
+        let unmatched: WeakStreamSlot<A> = Arc::new(RwLock::new(None));
+        let changes: WeakStreamSlot<RouterChange<K>> = Arc::new(RwLock::new(None));
         {
             let sodium_ctx2 = sodium_ctx.clone();
             let table = table.clone();
+            let unmatched = unmatched.clone();
+            let changes = changes.clone();
             let in_stream2 = in_stream.clone();
             node = Node::new(
                 sodium_ctx,
                 "Router",
                 move || {
                     let sodium_ctx = &sodium_ctx2;
-                    let keys_firing_op = in_stream2.with_firing_op(|firing_op: &mut Option<A>| {
-                        if let Some(ref firing) = firing_op {
-                            Some((selector(firing), firing.clone()))
-                        } else {
-                            None
-                        }
-                    });
-                    if let Some((keys, firing)) = keys_firing_op {
-                        for key in keys {
+                    let firing_op =
+                        in_stream2.with_firing_op(|firing_op: &mut Option<A>| firing_op.clone());
+                    if let Some(firing) = firing_op {
+                        let mut matched = false;
+                        select(&firing, &mut |key: K| {
                             let mut table = table.write().unwrap();
                             let mut remove_it = false;
                             if let Some(weak_stream) = table.get(&key) {
                                 if let Some(stream) = weak_stream.upgrade() {
-                                    stream._send(firing.clone());
-                                    sodium_ctx.with_data(|data: &mut SodiumCtxData| {
-                                        data.changed_nodes.push(stream.box_clone());
-                                    });
+                                    stream._send_changed(firing.clone());
+                                    matched = true;
                                 } else {
                                     remove_it = true;
                                 }
                             }
                             if remove_it {
                                 table.remove(&key);
+                                drop(table);
+                                notify_change(sodium_ctx, &changes, RouterChange::Removed(key));
+                            }
+                        });
+                        if !matched {
+                            let unmatched_op = unmatched
+                                .read()
+                                .unwrap()
+                                .as_ref()
+                                .and_then(|weak_stream| weak_stream.upgrade());
+                            if let Some(stream) = unmatched_op {
+                                stream._send_changed(firing);
                             }
                         }
                     }
                 },
                 vec![in_stream.box_clone()],
             );
-            IsNode::add_update_dependencies(&node, vec![in_stream.to_dep()]);
+            <dyn IsNode>::add_update_dependencies(&node, vec![in_stream.to_dep()]);
         }
         Router {
             sodium_ctx: sodium_ctx.clone(),
             table,
+            unmatched,
+            changes,
             node,
         }
     }
 
     pub fn filter_matches(&self, k: &K) -> Stream<A>
+    where
+        A: Clone + Send + 'static,
+        K: Clone + Send + Sync + Eq + Hash + 'static,
+    {
+        // Run in a transaction so that the changes of the active keys
+        // fire once it ends.
+        self.sodium_ctx.transaction(|| self._filter_matches(k))
+    }
+
+    fn _filter_matches(&self, k: &K) -> Stream<A>
     where
         A: Clone + Send + 'static,
         K: Clone + Send + Sync + Eq + Hash + 'static,
     {
         let mut table = self.table.write().unwrap();
         let existing_op;
+        let replaces_dead;
         if let Some(weak_stream) = table.get(k) {
-            if let Some(stream) = weak_stream.upgrade() {
-                existing_op = Some(stream);
-            } else {
-                existing_op = None;
-            }
+            existing_op = weak_stream.upgrade();
+            replaces_dead = existing_op.is_none();
         } else {
             existing_op = None;
+            replaces_dead = false;
         }
         if let Some(existing) = existing_op {
             existing
@@ -164,7 +254,9 @@ impl<A, K> Router<A, K> {
                 .push(self.box_clone());
             table.insert(k.clone(), Stream::downgrade(&s));
             {
+                let sodium_ctx = self.sodium_ctx.clone();
                 let table = self.table.clone();
+                let changes = self.changes.clone();
                 let k = k.clone();
                 let weak_s = Stream::downgrade(&s);
                 s.node()
@@ -182,10 +274,322 @@ impl<A, K> Router<A, K> {
                         }
                         if remove_it {
                             table.remove(&k);
+                            drop(table);
+                            notify_change(&sodium_ctx, &changes, RouterChange::Removed(k.clone()));
                         }
                     }))
             }
+            drop(table);
+            // The dead stream's cleanup will no longer find its entry,
+            // so report its removal here.
+            if replaces_dead {
+                notify_change(
+                    &self.sodium_ctx,
+                    &self.changes,
+                    RouterChange::Removed(k.clone()),
+                );
+            }
+            notify_change(
+                &self.sodium_ctx,
+                &self.changes,
+                RouterChange::Added(k.clone()),
+            );
             s
         }
     }
+
+    pub fn unmatched(&self) -> Stream<A>
+    where
+        A: Clone + Send + 'static,
+        K: Send + Sync + 'static,
+    {
+        let mut unmatched = self.unmatched.write().unwrap();
+        if let Some(stream) = unmatched
+            .as_ref()
+            .and_then(|weak_stream| weak_stream.upgrade())
+        {
+            return stream;
+        }
+        let s = Stream::new(&self.sodium_ctx);
+        s.node()
+            .data()
+            .dependencies
+            .write()
+            .unwrap()
+            .push(self.box_clone());
+        *unmatched = Some(Stream::downgrade(&s));
+        s
+    }
+
+    pub fn active_keys(&self) -> Vec<K>
+    where
+        K: Clone,
+    {
+        self.table
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, weak_stream)| weak_stream.upgrade().is_some())
+            .map(|(k, _)| k.clone())
+            .collect()
+    }
+
+    pub fn changes(&self) -> Stream<RouterChange<K>>
+    where
+        K: Send + 'static,
+    {
+        let mut changes = self.changes.write().unwrap();
+        if let Some(stream) = changes
+            .as_ref()
+            .and_then(|weak_stream| weak_stream.upgrade())
+        {
+            return stream;
+        }
+        let s = Stream::new(&self.sodium_ctx);
+        *changes = Some(Stream::downgrade(&s));
+        s
+    }
+}
+
+// The subscriptions of an ordered router. Those to a single key are
+// looked up directly, and those to a range in an interval index, so an
+// event only visits the subscriptions that contain its key.
+struct OrderedTable<A, K> {
+    exact: BTreeMap<K, WeakStream<A>>,
+    ranges: IntervalIndex<K, WeakStream<A>>,
+}
+
+type OrderedTableRef<A, K> = Arc<RwLock<OrderedTable<A, K>>>;
+
+pub struct OrderedRouter<A, K> {
+    sodium_ctx: SodiumCtx,
+    table: OrderedTableRef<A, K>,
+    node: Node,
+}
+
+impl<A, K> Clone for OrderedRouter<A, K> {
+    fn clone(&self) -> Self {
+        OrderedRouter {
+            sodium_ctx: self.sodium_ctx.clone(),
+            table: self.table.clone(),
+            node: self.node.clone(),
+        }
+    }
+}
+
+impl<A, K> OrderedRouter<A, K> {
+    pub fn new(
+        sodium_ctx: &SodiumCtx,
+        in_stream: &Stream<A>,
+        selector: impl Fn(&A) -> Option<K> + Send + Sync + 'static,
+    ) -> OrderedRouter<A, K>
+    where
+        A: Clone + Send + 'static,
+        K: Clone + Ord + Send + Sync + 'static,
+    {
+        let node;
+        let table: OrderedTableRef<A, K> = Arc::new(RwLock::new(OrderedTable {
+            exact: BTreeMap::new(),
+            ranges: IntervalIndex::new(),
+        }));
+        {
+            let table = table.clone();
+            let in_stream2 = in_stream.clone();
+            node = Node::new(
+                sodium_ctx,
+                "OrderedRouter",
+                move || {
+                    let keys_firing_op = in_stream2.with_firing_op(|firing_op: &mut Option<A>| {
+                        let firing = firing_op.as_ref()?;
+                        Some((selector(firing)?, firing.clone()))
+                    });
+                    if let Some((key, firing)) = keys_firing_op {
+                        let mut streams: Vec<Stream<A>> = Vec::new();
+                        {
+                            let table = table.read().unwrap();
+                            streams.extend(
+                                table
+                                    .exact
+                                    .get(&key)
+                                    .and_then(|weak_stream| weak_stream.upgrade()),
+                            );
+                            table.ranges.for_each_containing(&key, |weak_stream| {
+                                streams.extend(weak_stream.upgrade());
+                            });
+                        }
+                        for stream in streams {
+                            stream._send_changed(firing.clone());
+                        }
+                    }
+                },
+                vec![in_stream.box_clone()],
+            );
+            <dyn IsNode>::add_update_dependencies(&node, vec![in_stream.to_dep()]);
+        }
+        OrderedRouter {
+            sodium_ctx: sodium_ctx.clone(),
+            table,
+            node,
+        }
+    }
+
+    pub fn filter_matches(&self, k: &K) -> Stream<A>
+    where
+        A: Clone + Send + 'static,
+        K: Clone + Ord + Send + Sync + 'static,
+    {
+        let mut table = self.table.write().unwrap();
+        if let Some(existing) = table
+            .exact
+            .get(k)
+            .and_then(|weak_stream| weak_stream.upgrade())
+        {
+            return existing;
+        }
+        let s = self.new_subscriber();
+        table.exact.insert(k.clone(), Stream::downgrade(&s));
+        {
+            let table = self.table.clone();
+            let k = k.clone();
+            let weak_s = Stream::downgrade(&s);
+            s.node()
+                .data()
+                .cleanups
+                .write()
+                .unwrap()
+                .push(Box::new(move || {
+                    let mut table = table.write().unwrap();
+                    let mut remove_it = false;
+                    if let Some(weak_stream) = table.exact.get(&k) {
+                        remove_it = weak_stream.data.ptr_eq(&weak_s.data);
+                    }
+                    if remove_it {
+                        table.exact.remove(&k);
+                    }
+                }))
+        }
+        s
+    }
+
+    pub fn filter_range<R: RangeBounds<K>>(&self, range: R) -> Stream<A>
+    where
+        A: Clone + Send + 'static,
+        K: Clone + Ord + Send + Sync + 'static,
+    {
+        if let (Bound::Included(start), Bound::Included(end)) =
+            (range.start_bound(), range.end_bound())
+        {
+            if start == end {
+                return self.filter_matches(start);
+            }
+        }
+        let start = Start::from_bound(range.start_bound());
+        let end = End::from_bound(range.end_bound());
+        let mut table = self.table.write().unwrap();
+        if let Some(existing) = table
+            .ranges
+            .get(&start, &end)
+            .and_then(|weak_stream| weak_stream.upgrade())
+        {
+            return existing;
+        }
+        let s = self.new_subscriber();
+        table
+            .ranges
+            .insert(start.clone(), end.clone(), Stream::downgrade(&s));
+        {
+            let table = self.table.clone();
+            let weak_s = Stream::downgrade(&s);
+            s.node()
+                .data()
+                .cleanups
+                .write()
+                .unwrap()
+                .push(Box::new(move || {
+                    let mut table = table.write().unwrap();
+                    let mut remove_it = false;
+                    if let Some(weak_stream) = table.ranges.get(&start, &end) {
+                        remove_it = weak_stream.data.ptr_eq(&weak_s.data);
+                    }
+                    if remove_it {
+                        table.ranges.remove(&start, &end);
+                    }
+                }))
+        }
+        s
+    }
+
+    fn new_subscriber(&self) -> Stream<A>
+    where
+        A: Clone + Send + 'static,
+        K: Send + Sync + 'static,
+    {
+        let s = Stream::new(&self.sodium_ctx);
+        s.node()
+            .data()
+            .dependencies
+            .write()
+            .unwrap()
+            .push(self.node.box_clone());
+        s
+    }
+}
+
+impl<A> OrderedRouter<A, String> {
+    pub fn filter_prefix(&self, prefix: &str) -> Stream<A>
+    where
+        A: Clone + Send + 'static,
+    {
+        match prefix_end(prefix) {
+            Some(end) => self.filter_range(prefix.to_string()..end),
+            None => self.filter_range(prefix.to_string()..),
+        }
+    }
+}
+
+// The least string greater than all those that start with the prefix,
+// or `None` if there is no such string.
+fn prefix_end(prefix: &str) -> Option<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+    while let Some(c) = chars.pop() {
+        let next = (c as u32 + 1..=char::MAX as u32).find_map(std::char::from_u32);
+        if let Some(next) = next {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
+    }
+    None
+}
+
+// Queue a change of the active keys on the context. Inside a
+// transaction it fires in a transaction of its own once the current one
+// ends. Otherwise, as when a stream is freed by garbage collection, it
+// fires at the start of the next transaction.
+fn notify_change<K: Send + 'static>(
+    sodium_ctx: &SodiumCtx,
+    changes: &WeakStreamSlot<RouterChange<K>>,
+    change: RouterChange<K>,
+) {
+    let stream_op = changes
+        .read()
+        .unwrap()
+        .as_ref()
+        .and_then(|weak_stream| weak_stream.upgrade());
+    let stream = match stream_op {
+        Some(stream) => stream,
+        None => return,
+    };
+    let in_transaction =
+        sodium_ctx.with_data(|data: &mut SodiumCtxData| data.transaction_depth > 0);
+    let mut change_op = Some(change);
+    let send = move || {
+        if let Some(change) = change_op.take() {
+            stream._send_changed(change);
+        }
+    };
+    if in_transaction {
+        sodium_ctx.post(send);
+    } else {
+        sodium_ctx.pre_eot(send);
+    }
 }
@@ -1,10 +1,13 @@
-use crate::impl_::gc_node::GcCtx;
+use crate::impl_::gc_node::{GcCtx, GcNode};
+use crate::impl_::history::History;
 use crate::impl_::listener::Listener;
 use crate::impl_::node::{
-    box_clone_vec_is_node, box_clone_vec_is_weak_node, IsNode, IsWeakNode, Node,
+    box_clone_vec_is_node, box_clone_vec_is_weak_node, IsNode, IsWeakNode, Node, NodeData,
 };
+use crate::impl_::state_store::StateStore;
 
 use std::mem;
+use std::sync::atomic::AtomicBool;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
@@ -18,12 +21,19 @@ pub struct SodiumCtx {
     node_count: Arc<AtomicUsize>,
     node_ref_count: Arc<AtomicUsize>,
     threaded_mode: Arc<ThreadedMode>,
+    // Mirrors `dependency_validation != Off`, so that `Cell::sample`
+    // can skip validation without taking the lock.
+    validating_dependencies: Arc<AtomicBool>,
+    state_store: StateStore,
+    history: History,
 }
 
 pub struct SodiumCtxData {
     pub changed_nodes: Vec<Box<dyn IsNode>>,
     pub visited_nodes: Vec<Box<dyn IsNode>>,
     pub transaction_depth: u32,
+    // Incremented as each outermost transaction starts.
+    pub transaction_id: u64,
     pub pre_eot: Vec<Box<dyn FnMut() + Send>>,
     pub pre_post: Vec<Box<dyn FnMut() + Send>>,
     pub post: Vec<Box<dyn FnMut() + Send>>,
@@ -31,6 +41,31 @@ pub struct SodiumCtxData {
     pub collecting_cycles: bool,
     pub allow_add_roots: bool,
     pub allow_collect_cycles_counter: u32,
+    pub dependency_validation: DependencyValidation,
+    pub updating_nodes: Vec<UpdatingNode>,
+}
+
+/// How [`Cell::sample`][crate::Cell::sample] reports a sampled cell
+/// that was not declared as a dependency of the node whose update is
+/// currently running.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DependencyValidation {
+    /// Do not check samples (the default).
+    Off,
+    /// Log a warning naming both nodes.
+    Log,
+    /// Panic with a message naming both nodes.
+    Panic,
+}
+
+// A node whose update closure is running while dependency validation
+// is enabled.
+#[derive(Clone)]
+pub struct UpdatingNode {
+    pub data: Arc<NodeData>,
+    pub gc_node: GcNode,
+    // gc nodes with this id or greater were created during the update
+    pub first_new_id: u32,
 }
 
 pub struct ThreadedMode {
@@ -128,6 +163,7 @@ impl SodiumCtx {
                 changed_nodes: Vec::new(),
                 visited_nodes: Vec::new(),
                 transaction_depth: 0,
+                transaction_id: 0,
                 pre_eot: Vec::new(),
                 pre_post: Vec::new(),
                 post: Vec::new(),
@@ -135,10 +171,15 @@ impl SodiumCtx {
                 collecting_cycles: false,
                 allow_add_roots: true,
                 allow_collect_cycles_counter: 0,
+                dependency_validation: DependencyValidation::Off,
+                updating_nodes: Vec::new(),
             })),
             node_count: Arc::new(AtomicUsize::new(0)),
             node_ref_count: Arc::new(AtomicUsize::new(0)),
             threaded_mode: Arc::new(single_threaded_mode()),
+            validating_dependencies: Arc::new(AtomicBool::new(false)),
+            state_store: StateStore::new(),
+            history: History::new(),
         }
     }
 
@@ -146,6 +187,14 @@ impl SodiumCtx {
         self.gc_ctx.clone()
     }
 
+    pub fn state_store(&self) -> StateStore {
+        self.state_store.clone()
+    }
+
+    pub fn history(&self) -> History {
+        self.history.clone()
+    }
+
     pub fn null_node(&self) -> Node {
         Node::new(self, "null_node", || {}, Vec::new())
     }
@@ -159,10 +208,17 @@ impl SodiumCtx {
 
     pub fn enter_transaction(&self) {
         self.with_data(|data: &mut SodiumCtxData| {
+            if data.transaction_depth == 0 {
+                data.transaction_id += 1;
+            }
             data.transaction_depth += 1;
         });
     }
 
+    pub fn transaction_id(&self) -> u64 {
+        self.with_data(|data: &mut SodiumCtxData| data.transaction_id)
+    }
+
     pub fn leave_transaction(&self) {
         let is_end_of_transaction = self.with_data(|data: &mut SodiumCtxData| {
             data.transaction_depth -= 1;
@@ -261,6 +317,9 @@ impl SodiumCtx {
                 self.update_node(node.node());
             }
         }
+        if self.history.is_enabled() {
+            self.history.end_of_transaction(self.transaction_id());
+        }
         self.with_data(|data: &mut SodiumCtxData| {
             data.transaction_depth -= 1;
         });
@@ -342,9 +401,7 @@ impl SodiumCtx {
                 });
         // if dependencies changed, then execute update on current node
         if any_changed {
-            let mut update = node.data.update.write().unwrap();
-            let update: &mut Box<_> = &mut *update;
-            update();
+            self.run_update(node);
         }
         // if self changed then update dependents
         if *node.data.changed.read().unwrap() {
@@ -360,6 +417,98 @@ impl SodiumCtx {
         }
     }
 
+    pub fn run_update(&self, node: &Node) {
+        // Pops the updating node even if the update panics.
+        struct UpdatingNodeGuard<'a>(&'a SodiumCtx);
+        impl Drop for UpdatingNodeGuard<'_> {
+            fn drop(&mut self) {
+                self.0.with_data(|data: &mut SodiumCtxData| {
+                    data.updating_nodes.pop();
+                });
+            }
+        }
+        let _guard = if self.validating_dependencies.load(Ordering::Relaxed) {
+            self.with_data(|data: &mut SodiumCtxData| {
+                data.updating_nodes.push(UpdatingNode {
+                    data: node.data.clone(),
+                    gc_node: node.gc_node.clone(),
+                    first_new_id: self.gc_ctx.next_id(),
+                });
+            });
+            Some(UpdatingNodeGuard(self))
+        } else {
+            None
+        };
+        let mut update = node.data.update.write().unwrap();
+        let update: &mut Box<_> = &mut *update;
+        update();
+    }
+
+    pub fn set_dependency_validation(&self, dependency_validation: DependencyValidation) {
+        self.with_data(|data: &mut SodiumCtxData| {
+            data.dependency_validation = dependency_validation;
+        });
+        self.validating_dependencies.store(
+            dependency_validation != DependencyValidation::Off,
+            Ordering::Relaxed,
+        );
+    }
+
+    // Check that a cell being sampled is a declared or structural
+    // dependency of the node whose update is currently running.
+    pub fn validate_sample(&self, node: &Node) {
+        if !self.validating_dependencies.load(Ordering::Relaxed) {
+            return;
+        }
+        let (dependency_validation, updating_node_op) =
+            self.with_data(|data: &mut SodiumCtxData| {
+                (
+                    data.dependency_validation,
+                    data.updating_nodes.last().cloned(),
+                )
+            });
+        let updating_node = match updating_node_op {
+            Some(x) => x,
+            None => return,
+        };
+        if dependency_validation == DependencyValidation::Off
+            || node.gc_node.id() >= updating_node.first_new_id
+            || Arc::ptr_eq(&updating_node.data, &node.data)
+            || updating_node.data.is_listener.load(Ordering::Relaxed)
+        {
+            return;
+        }
+        let is_update_dependency = updating_node
+            .data
+            .update_dependencies
+            .read()
+            .unwrap()
+            .iter()
+            .any(|dep| dep.gc_node().id() == node.gc_node.id());
+        let is_dependency = updating_node
+            .data
+            .dependencies
+            .read()
+            .unwrap()
+            .iter()
+            .any(|dep| Arc::ptr_eq(dep.data(), &node.data));
+        if is_update_dependency || is_dependency {
+            return;
+        }
+        let msg = format!(
+            "Cell::sample called on undeclared dependency {} (gc node {}) while updating {} (gc node {})",
+            node.gc_node.name(),
+            node.gc_node.id(),
+            updating_node.gc_node.name(),
+            updating_node.gc_node.id(),
+        );
+        match dependency_validation {
+            DependencyValidation::Off => {}
+            DependencyValidation::Log => warn!("{}", msg),
+            DependencyValidation::Panic => panic!("{}", msg),
+        }
+    }
+
     pub fn collect_cycles(&self) {
         self.gc_ctx.collect_cycles();
     }
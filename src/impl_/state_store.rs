@@ -0,0 +1,77 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+// Encodes the current value of a registered node, or returns `None`
+// once the node has been freed.
+pub type Sampler = Box<dyn Fn() -> Option<String> + Send + Sync>;
+
+#[derive(Clone, Default)]
+pub struct StateStore {
+    data: Arc<Mutex<StateStoreData>>,
+}
+
+#[derive(Default)]
+struct StateStoreData {
+    entries: BTreeMap<String, Arc<Sampler>>,
+    restored: HashMap<String, String>,
+}
+
+impl StateStore {
+    pub fn new() -> StateStore {
+        StateStore::default()
+    }
+
+    pub fn register(&self, name: &str, sampler: Sampler) {
+        let mut data = self.data.lock().unwrap();
+        data.entries.insert(name.to_string(), Arc::new(sampler));
+    }
+
+    pub fn take_restored(&self, name: &str) -> Option<String> {
+        let mut data = self.data.lock().unwrap();
+        data.restored.remove(name)
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, String> {
+        // Sample outside the lock, since sampling a cell may register
+        // further state.
+        let entries: Vec<(String, Arc<Sampler>)> = {
+            let data = self.data.lock().unwrap();
+            data.entries
+                .iter()
+                .map(|(name, sampler)| (name.clone(), sampler.clone()))
+                .collect()
+        };
+        let mut result = BTreeMap::new();
+        let mut dead = Vec::new();
+        for (name, sampler) in entries {
+            match sampler() {
+                Some(value) => {
+                    result.insert(name, value);
+                }
+                None => dead.push((name, sampler)),
+            }
+        }
+        if !dead.is_empty() {
+            let mut data = self.data.lock().unwrap();
+            for (name, sampler) in dead {
+                // Only prune the entry if it wasn't replaced meanwhile.
+                if let Some(entry) = data.entries.get(&name) {
+                    if Arc::ptr_eq(entry, &sampler) {
+                        data.entries.remove(&name);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    pub fn restore(&self, state: BTreeMap<String, String>) {
+        let mut data = self.data.lock().unwrap();
+        data.restored = state.into_iter().collect();
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let data = self.data.lock().unwrap();
+        data.entries.keys().cloned().collect()
+    }
+}
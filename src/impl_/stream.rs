@@ -6,10 +6,13 @@ use crate::impl_::lambda::{lambda1, lambda1_deps, lambda2_deps};
 use crate::impl_::lazy::Lazy;
 use crate::impl_::listener::Listener;
 use crate::impl_::node::{box_clone_vec_is_node, IsNode, IsWeakNode, Node, WeakNode};
-use crate::impl_::sodium_ctx::SodiumCtx;
+use crate::impl_::sodium_ctx::{SodiumCtx, SodiumCtxData};
 use crate::impl_::stream_loop::StreamLoop;
 use crate::impl_::stream_sink::StreamSink;
+use crate::impl_::these::These;
 
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::RwLock;
@@ -49,6 +52,11 @@ impl<A: Send + 'static> StreamWeakForwardRef<A> {
         let x = self.data.read().unwrap();
         (&*x).clone().unwrap().upgrade().unwrap()
     }
+
+    pub fn upgrade(&self) -> Option<Stream<A>> {
+        let x = self.data.read().unwrap();
+        x.as_ref().and_then(WeakStream::upgrade)
+    }
 }
 
 pub struct Stream<A> {
@@ -107,10 +115,12 @@ impl<A> Clone for WeakStream<A> {
     }
 }
 
+type Coalescer<A> = Box<dyn FnMut(&A, &A) -> A + Send>;
+
 pub struct StreamData<A> {
     pub firing_op: Option<A>,
     pub sodium_ctx: SodiumCtx,
-    pub coalescer_op: Option<Box<dyn FnMut(&A, &A) -> A + Send>>,
+    pub coalescer_op: Option<Coalescer<A>>,
 }
 
 impl<
@@ -126,7 +136,10 @@ impl<
             let sodium_ctx = sodium_ctx.clone();
             let ss = StreamSink::downgrade(&ss);
             let listener = self.listen_weak(move |collection: &COLLECTION| {
-                let ss = ss.upgrade().unwrap();
+                let ss = match ss.upgrade() {
+                    Some(ss) => ss,
+                    None => return,
+                };
                 let iter = collection.clone().into_iter();
                 for a in iter {
                     let ss = ss.clone();
@@ -137,6 +150,59 @@ impl<
             s
         })
     }
+
+    pub fn split_each(&self) -> Stream<A> {
+        let sodium_ctx = self.sodium_ctx();
+        sodium_ctx.transaction(|| {
+            let ss = StreamSink::new(&sodium_ctx);
+            let s = ss.stream();
+            let sodium_ctx = sodium_ctx.clone();
+            let ss = StreamSink::downgrade(&ss);
+            // A single queue keeps the elements in order even when the
+            // input fires again from a transaction started by an earlier
+            // post callback.
+            let queue: Arc<Mutex<SplitQueue<A>>> = Arc::new(Mutex::new(SplitQueue {
+                pending: VecDeque::new(),
+                scheduled: false,
+            }));
+            let listener = self.listen_weak(move |collection: &COLLECTION| {
+                let schedule = {
+                    let mut queue = queue.lock().unwrap();
+                    queue.pending.extend(collection.clone());
+                    let schedule = !queue.scheduled && !queue.pending.is_empty();
+                    queue.scheduled |= schedule;
+                    schedule
+                };
+                if schedule {
+                    let queue = queue.clone();
+                    let ss = ss.clone();
+                    sodium_ctx.post(move || loop {
+                        let a = {
+                            let mut queue = queue.lock().unwrap();
+                            match queue.pending.pop_front() {
+                                Some(a) => a,
+                                None => {
+                                    queue.scheduled = false;
+                                    return;
+                                }
+                            }
+                        };
+                        match ss.upgrade() {
+                            Some(ss) => ss.send(a),
+                            None => {
+                                let mut queue = queue.lock().unwrap();
+                                queue.pending.clear();
+                                queue.scheduled = false;
+                                return;
+                            }
+                        }
+                    });
+                }
+            });
+            <dyn IsNode>::add_keep_alive(&s, &listener.gc_node);
+            s
+        })
+    }
 }
 
 impl<A> Stream<A> {
@@ -210,11 +276,7 @@ impl<A: Send + 'static> Stream<A> {
                 let s = s.clone();
                 let sodium_ctx2 = sodium_ctx.clone();
                 sodium_ctx.pre_eot(move || {
-                    {
-                        let mut update = node.data.update.write().unwrap();
-                        let update: &mut Box<_> = &mut update;
-                        update();
-                    }
+                    sodium_ctx2.run_update(&node);
                     let is_firing =
                         s.with_data(|data: &mut StreamData<A>| data.firing_op.is_some());
                     if is_firing {
@@ -314,6 +376,224 @@ impl<A: Send + 'static> Stream<A> {
         })
     }
 
+    pub fn filter_map<B: Send + 'static, FN: IsLambda1<A, Option<B>> + Send + Sync + 'static>(
+        &self,
+        mut f: FN,
+    ) -> Stream<B> {
+        let self_ = self.clone();
+        let sodium_ctx = self.sodium_ctx();
+        Stream::_new(&sodium_ctx, |s: StreamWeakForwardRef<B>| {
+            let f_deps = lambda1_deps(&f);
+            let node = Node::new(
+                &sodium_ctx,
+                "Stream::filter_map",
+                move || {
+                    self_.with_firing_op(|firing_op: &mut Option<A>| {
+                        if let Some(b) = firing_op.as_ref().and_then(|firing| f.call(firing)) {
+                            s.unwrap()._send(b);
+                        }
+                    })
+                },
+                vec![self.box_clone()],
+            );
+            <dyn IsNode>::add_update_dependencies(&node, f_deps);
+            <dyn IsNode>::add_update_dependencies(&node, vec![self.to_dep()]);
+            node
+        })
+    }
+
+    pub fn partition<PRED: IsLambda1<A, bool> + Send + Sync + 'static>(
+        &self,
+        mut pred: PRED,
+    ) -> (Stream<A>, Stream<A>)
+    where
+        A: Clone,
+    {
+        let sodium_ctx = self.sodium_ctx();
+        sodium_ctx.transaction(|| {
+            let true_ref: StreamWeakForwardRef<A> = StreamWeakForwardRef::new();
+            let false_ref: StreamWeakForwardRef<A> = StreamWeakForwardRef::new();
+            // The predicate is evaluated once in this node, which sends
+            // each event straight to one of the two outputs. The outputs
+            // themselves have no update of their own.
+            let split: Stream<()> = {
+                let self_ = self.clone();
+                let true_ref = true_ref.clone();
+                let false_ref = false_ref.clone();
+                Stream::_new(&sodium_ctx, |s: StreamWeakForwardRef<()>| {
+                    let pred_deps = lambda1_deps(&pred);
+                    let node = Node::new(
+                        &sodium_ctx,
+                        "Stream::partition",
+                        move || {
+                            self_.with_firing_op(|firing_op: &mut Option<A>| {
+                                if let Some(ref firing) = firing_op {
+                                    let out_ref = if pred.call(firing) {
+                                        &true_ref
+                                    } else {
+                                        &false_ref
+                                    };
+                                    if let Some(out) = out_ref.upgrade() {
+                                        out._send(firing.clone());
+                                    }
+                                    s.unwrap()._send(());
+                                }
+                            })
+                        },
+                        vec![self.box_clone()],
+                    );
+                    <dyn IsNode>::add_update_dependencies(&node, pred_deps);
+                    <dyn IsNode>::add_update_dependencies(&node, vec![self.to_dep()]);
+                    node
+                })
+            };
+            let mk_out = |out_ref: &StreamWeakForwardRef<A>| {
+                let out = Stream::_new(&sodium_ctx, |_s: StreamWeakForwardRef<A>| {
+                    Node::new(
+                        &sodium_ctx,
+                        "Stream::partition",
+                        || {},
+                        vec![split.box_clone()],
+                    )
+                });
+                out_ref.assign(&out);
+                out
+            };
+            (mk_out(&true_ref), mk_out(&false_ref))
+        })
+    }
+
+    pub fn group_by<K, FN>(&self, mut key_fn: FN) -> Stream<(K, Stream<A>)>
+    where
+        A: Clone,
+        K: Clone + Eq + Hash + Send + Sync + 'static,
+        FN: IsLambda1<A, K> + Send + Sync + 'static,
+    {
+        let self_ = self.clone();
+        let sodium_ctx = self.sodium_ctx();
+        Stream::_new(&sodium_ctx, |s: StreamWeakForwardRef<(K, Stream<A>)>| {
+            let key_fn_deps = lambda1_deps(&key_fn);
+            let groups: Groups<K, A> = Arc::new(Mutex::new(HashMap::new()));
+            let sodium_ctx2 = sodium_ctx.clone();
+            let node = Node::new(
+                &sodium_ctx,
+                "Stream::group_by",
+                move || {
+                    let sodium_ctx = &sodium_ctx2;
+                    let firing_op =
+                        self_.with_firing_op(|firing_op: &mut Option<A>| firing_op.clone());
+                    let a = match firing_op {
+                        Some(a) => a,
+                        None => return,
+                    };
+                    let k = key_fn.call(&a);
+                    let mut groups2 = groups.lock().unwrap();
+                    if let Some(group) = groups2.get_mut(&k) {
+                        if let Some(sub) = group.stream.upgrade() {
+                            if let Some(ref mut pending) = group.pending {
+                                pending.push_back(a);
+                            } else {
+                                drop(groups2);
+                                sub._send_changed(a);
+                            }
+                            return;
+                        }
+                    }
+                    // A new key, or one whose substream has been freed.
+                    let outer = s.unwrap();
+                    let sub = Stream::new(sodium_ctx);
+                    sub.node()
+                        .data()
+                        .dependencies
+                        .write()
+                        .unwrap()
+                        .push(outer.box_clone());
+                    groups2.insert(
+                        k.clone(),
+                        Group {
+                            stream: Stream::downgrade(&sub),
+                            pending: Some(VecDeque::from(vec![a])),
+                        },
+                    );
+                    drop(groups2);
+                    {
+                        let groups = groups.clone();
+                        let k = k.clone();
+                        let weak_sub = Stream::downgrade(&sub);
+                        sub.node()
+                            .data()
+                            .cleanups
+                            .write()
+                            .unwrap()
+                            .push(Box::new(move || {
+                                let mut groups = groups.lock().unwrap();
+                                let mut remove_it = false;
+                                if let Some(group) = groups.get(&k) {
+                                    if group.stream.data.ptr_eq(&weak_sub.data) {
+                                        remove_it = true;
+                                    }
+                                }
+                                if remove_it {
+                                    groups.remove(&k);
+                                }
+                            }));
+                    }
+                    // Nothing can observe the substream yet, so the event that
+                    // introduced the key is delivered in a later transaction,
+                    // along with any that arrive for the key before it is.
+                    {
+                        let groups = groups.clone();
+                        let k = k.clone();
+                        sodium_ctx.post(move || deliver_pending(&groups, &k));
+                    }
+                    outer._send((k, sub));
+                },
+                vec![self.box_clone()],
+            );
+            <dyn IsNode>::add_update_dependencies(&node, key_fn_deps);
+            <dyn IsNode>::add_update_dependencies(&node, vec![self.to_dep()]);
+            node
+        })
+    }
+
+    pub fn buffer_while(&self, closed: &Cell<bool>) -> Stream<Vec<A>>
+    where
+        A: Clone,
+    {
+        let self_ = self.clone();
+        let closed = closed.clone();
+        let closed_updates = closed.updates();
+        let sodium_ctx = self.sodium_ctx();
+        Stream::_new(&sodium_ctx, |s: StreamWeakForwardRef<Vec<A>>| {
+            let buffer: Mutex<Vec<A>> = Mutex::new(Vec::new());
+            let deps = vec![self.to_dep(), closed.to_dep(), closed_updates.to_dep()];
+            let dependencies = vec![self.box_clone(), closed_updates.box_clone()];
+            let node = Node::new(
+                &sodium_ctx,
+                "Stream::buffer_while",
+                move || {
+                    // The gate's new value takes effect in the same
+                    // transaction it is updated in.
+                    let is_closed = closed_updates
+                        .with_firing_op(|firing_op: &mut Option<bool>| *firing_op)
+                        .unwrap_or_else(|| closed.sample());
+                    let mut buffer = buffer.lock().unwrap();
+                    self_.with_firing_op(|firing_op: &mut Option<A>| {
+                        if let Some(ref firing) = firing_op {
+                            buffer.push(firing.clone());
+                        }
+                    });
+                    if !is_closed && !buffer.is_empty() {
+                        s.unwrap()._send(std::mem::take(&mut *buffer));
+                    }
+                },
+                dependencies,
+            );
+            <dyn IsNode>::add_update_dependencies(&node, deps);
+            node
+        })
+    }
+
     pub fn or_else(&self, s2: &Stream<A>) -> Stream<A>
     where
         A: Clone,
@@ -362,6 +642,89 @@ impl<A: Send + 'static> Stream<A> {
         })
     }
 
+    pub fn merge_with<B: Clone + Send + 'static>(&self, s2: &Stream<B>) -> Stream<These<A, B>>
+    where
+        A: Clone,
+    {
+        let self_ = self.clone();
+        let s2 = s2.clone();
+        let s2_node = s2.box_clone();
+        let s2_dep = s2.to_dep();
+        let sodium_ctx = self.sodium_ctx();
+        Stream::_new(&sodium_ctx, |s: StreamWeakForwardRef<These<A, B>>| {
+            let node = Node::new(
+                &sodium_ctx,
+                "Stream::merge_with",
+                move || {
+                    let firing1_op =
+                        self_.with_firing_op(|firing_op: &mut Option<A>| firing_op.clone());
+                    let firing2_op =
+                        s2.with_firing_op(|firing_op: &mut Option<B>| firing_op.clone());
+                    let these_op = match (firing1_op, firing2_op) {
+                        (Some(a), Some(b)) => Some(These::Both(a, b)),
+                        (Some(a), None) => Some(These::This(a)),
+                        (None, Some(b)) => Some(These::That(b)),
+                        (None, None) => None,
+                    };
+                    if let Some(these) = these_op {
+                        s.unwrap()._send(these);
+                    }
+                },
+                vec![self.box_clone(), s2_node],
+            );
+            <dyn IsNode>::add_update_dependencies(&node, vec![self.to_dep(), s2_dep]);
+            node
+        })
+    }
+
+    pub fn or_else_all(sodium_ctx: &SodiumCtx, streams: Vec<Stream<A>>) -> Stream<A>
+    where
+        A: Clone,
+    {
+        Stream::merge_all(sodium_ctx, streams, |lhs: &A, _rhs: &A| lhs.clone())
+    }
+
+    pub fn merge_all<FN: IsLambda2<A, A, A> + Send + Sync + 'static>(
+        sodium_ctx: &SodiumCtx,
+        streams: Vec<Stream<A>>,
+        mut f: FN,
+    ) -> Stream<A>
+    where
+        A: Clone,
+    {
+        let dependencies = streams.iter().map(|s| s.box_clone()).collect();
+        let deps: Vec<Dep> = streams.iter().map(Stream::to_dep).collect();
+        Stream::_new(sodium_ctx, |s: StreamWeakForwardRef<A>| {
+            let f_deps = lambda2_deps(&f);
+            let node = Node::new(
+                sodium_ctx,
+                "Stream::merge_all",
+                move || {
+                    // Fold the simultaneous firings left to right, the
+                    // same way a chain of binary merges would.
+                    let mut acc_op: Option<A> = None;
+                    for stream in &streams {
+                        stream.with_firing_op(|firing_op: &mut Option<A>| {
+                            if let Some(ref firing) = firing_op {
+                                acc_op = Some(match acc_op.take() {
+                                    Some(acc) => f.call(&acc, firing),
+                                    None => firing.clone(),
+                                });
+                            }
+                        });
+                    }
+                    if let Some(acc) = acc_op {
+                        s.unwrap()._send(acc);
+                    }
+                },
+                dependencies,
+            );
+            <dyn IsNode>::add_update_dependencies(&node, f_deps);
+            <dyn IsNode>::add_update_dependencies(&node, deps);
+            node
+        })
+    }
+
     pub fn hold(&self, a: A) -> Cell<A>
     where
         A: Clone,
@@ -437,36 +800,164 @@ impl<A: Send + 'static> Stream<A> {
     where
         A: Clone,
     {
-        let self_ = self.clone();
+        self.take(1)
+    }
+
+    pub fn take(&self, n: usize) -> Stream<A>
+    where
+        A: Clone,
+    {
         let sodium_ctx = self.sodium_ctx();
-        Stream::_new(&sodium_ctx, |s: StreamWeakForwardRef<A>| {
+        if n == 0 {
+            return Stream::new(&sodium_ctx);
+        }
+        let self_ = self.clone();
+        let mut count = 0;
+        Stream::_new_until(
+            &sodium_ctx,
+            if n == 1 {
+                "Stream::once"
+            } else {
+                "Stream::take"
+            },
+            vec![self.box_clone()],
+            vec![self.to_dep()],
+            move |s: &StreamWeakForwardRef<A>| {
+                self_.with_firing_op(|firing_op: &mut Option<A>| {
+                    if let Some(ref firing) = firing_op {
+                        s.unwrap()._send(firing.clone());
+                        count += 1;
+                    }
+                });
+                count >= n
+            },
+        )
+    }
+
+    pub fn take_while<PRED: IsLambda1<A, bool> + Send + Sync + 'static>(
+        &self,
+        mut pred: PRED,
+    ) -> Stream<A>
+    where
+        A: Clone,
+    {
+        let self_ = self.clone();
+        let mut deps = lambda1_deps(&pred);
+        deps.push(self.to_dep());
+        Stream::_new_until(
+            &self.sodium_ctx(),
+            "Stream::take_while",
+            vec![self.box_clone()],
+            deps,
+            move |s: &StreamWeakForwardRef<A>| {
+                self_.with_firing_op(|firing_op: &mut Option<A>| match firing_op {
+                    Some(ref firing) if pred.call(firing) => {
+                        s.unwrap()._send(firing.clone());
+                        false
+                    }
+                    Some(_) => true,
+                    None => false,
+                })
+            },
+        )
+    }
+
+    pub fn take_until<B: Send + 'static>(&self, s2: &Stream<B>) -> Stream<A>
+    where
+        A: Clone,
+    {
+        let self_ = self.clone();
+        let s2 = s2.clone();
+        Stream::_new_until(
+            &self.sodium_ctx(),
+            "Stream::take_until",
+            vec![self.box_clone(), s2.box_clone()],
+            vec![self.to_dep(), s2.to_dep()],
+            move |s: &StreamWeakForwardRef<A>| {
+                // An event in the same transaction as the stop event
+                // is dropped.
+                if s2.with_firing_op(|firing_op: &mut Option<B>| firing_op.is_some()) {
+                    return true;
+                }
+                self_.with_firing_op(|firing_op: &mut Option<A>| {
+                    if let Some(ref firing) = firing_op {
+                        s.unwrap()._send(firing.clone());
+                    }
+                });
+                false
+            },
+        )
+    }
+
+    pub fn skip(&self, n: usize) -> Stream<A>
+    where
+        A: Clone,
+    {
+        let mut skipped = 0;
+        self.filter(move |_: &A| {
+            if skipped < n {
+                skipped += 1;
+                false
+            } else {
+                true
+            }
+        })
+    }
+
+    pub fn skip_while<PRED: IsLambda1<A, bool> + Send + Sync + 'static>(
+        &self,
+        mut pred: PRED,
+    ) -> Stream<A>
+    where
+        A: Clone,
+    {
+        let pred_deps = lambda1_deps(&pred);
+        let mut skipping = true;
+        self.filter(lambda1(
+            move |a: &A| {
+                skipping = skipping && pred.call(a);
+                !skipping
+            },
+            pred_deps,
+        ))
+    }
+
+    // Create a stream whose update returns `true` once the stream has
+    // finished. From then on it drops its dependencies so that the
+    // upstream part of the graph can be garbage-collected.
+    fn _new_until<UPDATE: FnMut(&StreamWeakForwardRef<A>) -> bool + Send + Sync + 'static>(
+        sodium_ctx: &SodiumCtx,
+        name: &'static str,
+        dependencies: Vec<Box<dyn IsNode + Send + Sync>>,
+        deps: Vec<Dep>,
+        mut update: UPDATE,
+    ) -> Stream<A> {
+        Stream::_new(sodium_ctx, |s: StreamWeakForwardRef<A>| {
             let sodium_ctx = sodium_ctx.clone();
-            let sodium_ctx2 = sodium_ctx.clone();
+            let mut done = false;
             let node = Node::new(
-                &sodium_ctx2,
-                "Stream::once",
+                &sodium_ctx.clone(),
+                name,
                 move || {
-                    self_.with_firing_op(|firing_op: &mut Option<A>| {
-                        if let Some(ref firing) = firing_op {
-                            let s = s.unwrap();
-                            s._send(firing.clone());
-                            let node = s.box_clone();
-                            sodium_ctx.post(move || {
-                                let deps;
-                                {
-                                    let dependencies = node.data().dependencies.read().unwrap();
-                                    deps = box_clone_vec_is_node(&(*dependencies));
-                                }
-                                for dep in deps {
-                                    IsNode::remove_dependency(node.node(), dep.node());
-                                }
-                            });
+                    if done || !update(&s) {
+                        return;
+                    }
+                    done = true;
+                    let node = s.unwrap().box_clone();
+                    sodium_ctx.post(move || {
+                        let deps;
+                        {
+                            let dependencies = node.data().dependencies.read().unwrap();
+                            deps = box_clone_vec_is_node(&dependencies);
                         }
-                    })
+                        for dep in deps {
+                            <dyn IsNode>::remove_dependency(node.node(), dep.node());
+                        }
+                    });
                 },
-                vec![self.box_clone()],
+                dependencies,
             );
-            IsNode::add_update_dependencies(&node, vec![self.to_dep()]);
+            <dyn IsNode>::add_update_dependencies(&node, deps);
             node
         })
     }
@@ -544,6 +1035,18 @@ impl<A: Send + 'static> Stream<A> {
         });
     }
 
+    // Fire this stream from the update of a node it doesn't depend on,
+    // as a sink would be fired.
+    pub fn _send_changed(&self, a: A) {
+        let sodium_ctx = self.sodium_ctx();
+        sodium_ctx.transaction(|| {
+            self._send(a);
+            sodium_ctx.with_data(|data: &mut SodiumCtxData| {
+                data.changed_nodes.push(self.box_clone());
+            });
+        });
+    }
+
     pub fn downgrade(this: &Self) -> WeakStream<A> {
         WeakStream {
             data: Arc::downgrade(&this.data),
@@ -564,3 +1067,47 @@ impl<A> WeakStream<A> {
         Some(Stream { data, node })
     }
 }
+
+// The elements of `Stream::split_each` waiting to be sent, and whether
+// a post callback to send them has been registered.
+struct SplitQueue<A> {
+    pending: VecDeque<A>,
+    scheduled: bool,
+}
+
+// The substreams of `Stream::group_by` by key. `pending` holds the
+// events for a new key that are still to be delivered, each in a
+// transaction of its own.
+struct Group<A> {
+    stream: WeakStream<A>,
+    pending: Option<VecDeque<A>>,
+}
+
+type Groups<K, A> = Arc<Mutex<HashMap<K, Group<A>>>>;
+
+fn deliver_pending<K: Eq + Hash, A: Send + 'static>(groups: &Groups<K, A>, k: &K) {
+    loop {
+        let (sub_op, a) = {
+            let mut groups = groups.lock().unwrap();
+            let group = match groups.get_mut(k) {
+                Some(group) => group,
+                None => return,
+            };
+            match group
+                .pending
+                .as_mut()
+                .and_then(|pending| pending.pop_front())
+            {
+                Some(a) => (group.stream.upgrade(), a),
+                None => {
+                    group.pending = None;
+                    return;
+                }
+            }
+        };
+        match sub_op {
+            Some(sub) => sub._send_changed(a),
+            None => return,
+        }
+    }
+}
@@ -4,14 +4,28 @@ use crate::impl_::sodium_ctx::SodiumCtxData;
 use crate::impl_::stream::Stream;
 use crate::impl_::stream::WeakStream;
 
+#[cfg(feature = "recording")]
+use std::sync::{Arc, RwLock};
+
+// Called with each value sent, inside the transaction it is sent in.
+#[cfg(feature = "recording")]
+pub type RecordingFn<A> = Arc<dyn Fn(&A) + Send + Sync>;
+
+#[cfg(feature = "recording")]
+pub type RecordingHook<A> = Arc<RwLock<Option<RecordingFn<A>>>>;
+
 pub struct StreamSink<A> {
     stream: Stream<A>,
     sodium_ctx: SodiumCtx,
+    #[cfg(feature = "recording")]
+    recording_hook: RecordingHook<A>,
 }
 
 pub struct WeakStreamSink<A> {
     stream: WeakStream<A>,
     sodium_ctx: SodiumCtx,
+    #[cfg(feature = "recording")]
+    recording_hook: RecordingHook<A>,
 }
 
 impl<A> Clone for StreamSink<A> {
@@ -19,6 +33,19 @@ impl<A> Clone for StreamSink<A> {
         StreamSink {
             stream: self.stream.clone(),
             sodium_ctx: self.sodium_ctx.clone(),
+            #[cfg(feature = "recording")]
+            recording_hook: self.recording_hook.clone(),
+        }
+    }
+}
+
+impl<A> Clone for WeakStreamSink<A> {
+    fn clone(&self) -> Self {
+        WeakStreamSink {
+            stream: self.stream.clone(),
+            sodium_ctx: self.sodium_ctx.clone(),
+            #[cfg(feature = "recording")]
+            recording_hook: self.recording_hook.clone(),
         }
     }
 }
@@ -28,6 +55,8 @@ impl<A: Send + 'static> StreamSink<A> {
         StreamSink {
             stream: Stream::new(sodium_ctx),
             sodium_ctx: sodium_ctx.clone(),
+            #[cfg(feature = "recording")]
+            recording_hook: Arc::new(RwLock::new(None)),
         }
     }
 
@@ -38,6 +67,8 @@ impl<A: Send + 'static> StreamSink<A> {
         StreamSink {
             stream: Stream::_new_with_coalescer(sodium_ctx, coalescer),
             sodium_ctx: sodium_ctx.clone(),
+            #[cfg(feature = "recording")]
+            recording_hook: Arc::new(RwLock::new(None)),
         }
     }
 
@@ -45,8 +76,21 @@ impl<A: Send + 'static> StreamSink<A> {
         self.stream.clone()
     }
 
+    // The slot holding the hook, which can be cleared later without
+    // keeping this sink alive.
+    #[cfg(feature = "recording")]
+    pub fn recording_hook(&self) -> RecordingHook<A> {
+        self.recording_hook.clone()
+    }
+
     pub fn send(&self, a: A) {
         self.sodium_ctx.transaction(|| {
+            #[cfg(feature = "recording")]
+            {
+                if let Some(hook) = &*self.recording_hook.read().unwrap() {
+                    hook(&a);
+                }
+            }
             let node = self.stream();
             {
                 let mut changed = node.data().changed.write().unwrap();
@@ -63,6 +107,8 @@ impl<A: Send + 'static> StreamSink<A> {
         WeakStreamSink {
             stream: Stream::downgrade(&this.stream),
             sodium_ctx: this.sodium_ctx.clone(),
+            #[cfg(feature = "recording")]
+            recording_hook: this.recording_hook.clone(),
         }
     }
 }
@@ -70,8 +116,11 @@ impl<A: Send + 'static> StreamSink<A> {
 impl<A> WeakStreamSink<A> {
     pub fn upgrade(&self) -> Option<StreamSink<A>> {
         let sodium_ctx = self.sodium_ctx.clone();
-        self.stream
-            .upgrade()
-            .map(|stream: Stream<A>| StreamSink { stream, sodium_ctx })
+        self.stream.upgrade().map(|stream: Stream<A>| StreamSink {
+            stream,
+            sodium_ctx,
+            #[cfg(feature = "recording")]
+            recording_hook: self.recording_hook.clone(),
+        })
     }
 }
@@ -0,0 +1,35 @@
+/// The event produced by [`Stream::merge_with`][crate::Stream::merge_with]:
+/// a value from the left stream, the right stream, or both when they
+/// fire in the same transaction.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum These<A, B> {
+    /// Only the left stream fired.
+    This(A),
+    /// Only the right stream fired.
+    That(B),
+    /// Both streams fired in the same transaction.
+    Both(A, B),
+}
+
+impl<A, B> These<A, B> {
+    /// The value from the left stream, if it fired.
+    pub fn this(&self) -> Option<&A> {
+        match self {
+            These::This(a) | These::Both(a, _) => Some(a),
+            These::That(_) => None,
+        }
+    }
+
+    /// The value from the right stream, if it fired.
+    pub fn that(&self) -> Option<&B> {
+        match self {
+            These::That(b) | These::Both(_, b) => Some(b),
+            These::This(_) => None,
+        }
+    }
+
+    /// Returns `true` if both streams fired in the same transaction.
+    pub fn is_both(&self) -> bool {
+        matches!(self, These::Both(_, _))
+    }
+}
@@ -4,35 +4,49 @@
 #[macro_use]
 extern crate log;
 
+mod aggregate;
 mod cell;
 mod cell_loop;
+mod cell_map;
 mod cell_sink;
+mod cell_vec;
+mod hash_trie;
+mod history;
 mod impl_;
+mod list_tree;
 mod listener;
+mod monoid;
 mod operational;
+#[cfg(feature = "recording")]
+mod recording;
 mod router;
 mod sodium_ctx;
+mod state_store;
 mod stream;
 mod stream_loop;
 mod stream_sink;
 mod transaction;
 
 pub use self::cell::Cell;
+pub use self::cell::CellTuple;
+pub use self::cell::WeakCell;
 pub use self::cell_loop::CellLoop;
+pub use self::cell_map::CellMap;
+pub use self::cell_map::MapDiff;
 pub use self::cell_sink::CellSink;
-#[doc(hidden)]
+pub use self::cell_sink::WeakCellSink;
+pub use self::cell_vec::CellVec;
+pub use self::cell_vec::ListDiff;
+pub use self::history::History;
+pub use self::history::HistoryChange;
+pub use self::history::HistorySnapshot;
 pub use self::impl_::dep::Dep;
-#[doc(hidden)]
+pub use self::impl_::dep::Trace;
 pub use self::impl_::lambda::lambda1;
-#[doc(hidden)]
 pub use self::impl_::lambda::lambda2;
-#[doc(hidden)]
 pub use self::impl_::lambda::lambda3;
-#[doc(hidden)]
 pub use self::impl_::lambda::lambda4;
-#[doc(hidden)]
 pub use self::impl_::lambda::lambda5;
-#[doc(hidden)]
 pub use self::impl_::lambda::lambda6;
 pub use self::impl_::lambda::IsLambda1;
 pub use self::impl_::lambda::IsLambda2;
@@ -45,13 +59,28 @@ pub use self::impl_::lambda::Lambda;
 pub use self::impl_::lazy::Lazy;
 #[doc(hidden)]
 pub use self::impl_::node::Node;
+pub use self::impl_::router::RouterChange;
+pub use self::impl_::sodium_ctx::DependencyValidation;
+pub use self::impl_::these::These;
 pub use self::listener::Listener;
+pub use self::monoid::Max;
+pub use self::monoid::Min;
+pub use self::monoid::Monoid;
 pub use self::operational::Operational;
+#[cfg(feature = "recording")]
+pub use self::recording::Recorder;
+#[cfg(feature = "recording")]
+pub use self::recording::Replayer;
+pub use self::router::OrderedRouter;
 pub use self::router::Router;
 pub use self::sodium_ctx::SodiumCtx;
+pub use self::state_store::Codec;
+pub use self::state_store::StateStore;
 pub use self::stream::Stream;
+pub use self::stream::WeakStream;
 pub use self::stream_loop::StreamLoop;
 pub use self::stream_sink::StreamSink;
+pub use self::stream_sink::WeakStreamSink;
 pub use self::transaction::Transaction;
 
 #[cfg(test)]
@@ -0,0 +1,218 @@
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+// A list with O(log n) insertion, removal and lookup by index, stored
+// as an implicit treap whose nodes are shared through `Arc`s. Cloning
+// a `ListTree` is O(1), and editing a clone only copies the nodes on
+// the path to the edit, so a cell can hold one version while the
+// next is built from it.
+//
+// Each element also carries a weight, and the tree can count the
+// total weight of the elements before an index. `CellVec::filter`
+// uses this to translate indices into the filtered list.
+pub(crate) struct ListTree<A> {
+    root: Link<A>,
+}
+
+type Link<A> = Option<Arc<TreeNode<A>>>;
+
+#[derive(Clone)]
+struct TreeNode<A> {
+    value: A,
+    weight: usize,
+    priority: u64,
+    size: usize,
+    total_weight: usize,
+    left: Link<A>,
+    right: Link<A>,
+}
+
+impl<A> Clone for ListTree<A> {
+    fn clone(&self) -> Self {
+        ListTree {
+            root: self.root.clone(),
+        }
+    }
+}
+
+// Treap priorities only need to be well spread, so a counter run
+// through splitmix64 is enough.
+pub(crate) fn next_priority() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut z = COUNTER
+        .fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed)
+        .wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+fn size<A>(link: &Link<A>) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+fn total_weight<A>(link: &Link<A>) -> usize {
+    link.as_ref().map_or(0, |node| node.total_weight)
+}
+
+impl<A: Clone> TreeNode<A> {
+    fn update(&mut self) {
+        self.size = size(&self.left) + 1 + size(&self.right);
+        self.total_weight = total_weight(&self.left) + self.weight + total_weight(&self.right);
+    }
+}
+
+// Split off the first `k` elements.
+fn split<A: Clone>(link: Link<A>, k: usize) -> (Link<A>, Link<A>) {
+    match link {
+        None => (None, None),
+        Some(mut node) => {
+            let n = Arc::make_mut(&mut node);
+            let left_size = size(&n.left);
+            if k <= left_size {
+                let (l, r) = split(n.left.take(), k);
+                n.left = r;
+                n.update();
+                (l, Some(node))
+            } else {
+                let (l, r) = split(n.right.take(), k - left_size - 1);
+                n.right = l;
+                n.update();
+                (Some(node), r)
+            }
+        }
+    }
+}
+
+fn merge<A: Clone>(a: Link<A>, b: Link<A>) -> Link<A> {
+    match (a, b) {
+        (None, b) => b,
+        (a, None) => a,
+        (Some(mut a), Some(mut b)) => {
+            if a.priority > b.priority {
+                let n = Arc::make_mut(&mut a);
+                n.right = merge(n.right.take(), Some(b));
+                n.update();
+                Some(a)
+            } else {
+                let n = Arc::make_mut(&mut b);
+                n.left = merge(Some(a), n.left.take());
+                n.update();
+                Some(b)
+            }
+        }
+    }
+}
+
+fn set<A: Clone>(link: &mut Link<A>, i: usize, value: A, weight: usize) -> (A, usize) {
+    let n = Arc::make_mut(link.as_mut().unwrap());
+    let left_size = size(&n.left);
+    let old = if i < left_size {
+        set(&mut n.left, i, value, weight)
+    } else if i == left_size {
+        (
+            mem::replace(&mut n.value, value),
+            mem::replace(&mut n.weight, weight),
+        )
+    } else {
+        set(&mut n.right, i - left_size - 1, value, weight)
+    };
+    n.update();
+    old
+}
+
+fn push_values<A: Clone>(link: &Link<A>, out: &mut Vec<A>) {
+    if let Some(node) = link {
+        push_values(&node.left, out);
+        out.push(node.value.clone());
+        push_values(&node.right, out);
+    }
+}
+
+impl<A: Clone> ListTree<A> {
+    pub(crate) fn new() -> ListTree<A> {
+        ListTree { root: None }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    pub(crate) fn push(&mut self, value: A, weight: usize) {
+        let len = self.len();
+        self.insert(len, value, weight);
+    }
+
+    pub(crate) fn insert(&mut self, i: usize, value: A, weight: usize) {
+        assert!(i <= self.len(), "insertion index {} out of range", i);
+        let node = TreeNode {
+            value,
+            weight,
+            priority: next_priority(),
+            size: 1,
+            total_weight: weight,
+            left: None,
+            right: None,
+        };
+        let (l, r) = split(self.root.take(), i);
+        self.root = merge(merge(l, Some(Arc::new(node))), r);
+    }
+
+    pub(crate) fn remove(&mut self, i: usize) -> (A, usize) {
+        assert!(i < self.len(), "removal index {} out of range", i);
+        let (l, r) = split(self.root.take(), i);
+        let (m, r) = split(r, 1);
+        self.root = merge(l, r);
+        match Arc::try_unwrap(m.unwrap()) {
+            Ok(node) => (node.value, node.weight),
+            Err(node) => (node.value.clone(), node.weight),
+        }
+    }
+
+    // Replace the element at `i`, returning the old value and weight.
+    pub(crate) fn set(&mut self, i: usize, value: A, weight: usize) -> (A, usize) {
+        assert!(i < self.len(), "index {} out of range", i);
+        set(&mut self.root, i, value, weight)
+    }
+
+    pub(crate) fn get(&self, mut i: usize) -> &A {
+        assert!(i < self.len(), "index {} out of range", i);
+        let mut link = &self.root;
+        loop {
+            let node = link.as_ref().unwrap();
+            let left_size = size(&node.left);
+            if i < left_size {
+                link = &node.left;
+            } else if i == left_size {
+                return &node.value;
+            } else {
+                i -= left_size + 1;
+                link = &node.right;
+            }
+        }
+    }
+
+    // The total weight of the elements before index `i`.
+    pub(crate) fn weight_before(&self, mut i: usize) -> usize {
+        let mut result = 0;
+        let mut link = &self.root;
+        while let Some(node) = link {
+            let left_size = size(&node.left);
+            if i <= left_size {
+                link = &node.left;
+            } else {
+                result += total_weight(&node.left) + node.weight;
+                i -= left_size + 1;
+                link = &node.right;
+            }
+        }
+        result
+    }
+
+    pub(crate) fn to_vec(&self) -> Vec<A> {
+        let mut result = Vec::with_capacity(self.len());
+        push_values(&self.root, &mut result);
+        result
+    }
+}
@@ -0,0 +1,99 @@
+/// A type with an associative combining operation and an identity
+/// value for it.
+///
+/// Used by [`Stream::fold_monoid`][crate::Stream::fold_monoid] and
+/// the folds built on it, so that accumulations don't need an
+/// explicit seed and combining function. Numeric types combine by
+/// addition, and `String` and `Vec` by concatenation.
+pub trait Monoid {
+    /// The identity value, for which `empty().combine(a)` and
+    /// `a.combine(empty())` are both equal to `a`.
+    fn empty() -> Self;
+
+    /// Combine two values. This must be associative.
+    fn combine(&self, other: &Self) -> Self;
+}
+
+macro_rules! impl_monoid_for_number {
+    ($($t:ty),+) => {
+        $(
+            impl Monoid for $t {
+                fn empty() -> Self {
+                    0 as $t
+                }
+
+                fn combine(&self, other: &Self) -> Self {
+                    self + other
+                }
+            }
+        )+
+    };
+}
+
+impl_monoid_for_number!(i8, i16, i32, i64, i128, isize);
+impl_monoid_for_number!(u8, u16, u32, u64, u128, usize);
+impl_monoid_for_number!(f32, f64);
+
+impl Monoid for String {
+    fn empty() -> Self {
+        String::new()
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        let mut result = String::with_capacity(self.len() + other.len());
+        result.push_str(self);
+        result.push_str(other);
+        result
+    }
+}
+
+impl<A: Clone> Monoid for Vec<A> {
+    fn empty() -> Self {
+        Vec::new()
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        let mut result = Vec::with_capacity(self.len() + other.len());
+        result.extend_from_slice(self);
+        result.extend_from_slice(other);
+        result
+    }
+}
+
+/// A wrapper whose `Option` is a [`Monoid`] that keeps the greatest
+/// value, with `None` as the identity.
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub struct Max<A>(pub A);
+
+/// A wrapper whose `Option` is a [`Monoid`] that keeps the least
+/// value, with `None` as the identity.
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub struct Min<A>(pub A);
+
+impl<A: PartialOrd + Clone> Monoid for Option<Max<A>> {
+    fn empty() -> Self {
+        None
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Some(Max(a)), Some(Max(b))) if b > a => other.clone(),
+            (Some(_), _) => self.clone(),
+            (None, _) => other.clone(),
+        }
+    }
+}
+
+impl<A: PartialOrd + Clone> Monoid for Option<Min<A>> {
+    fn empty() -> Self {
+        None
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Some(Min(a)), Some(Min(b))) if b < a => other.clone(),
+            (Some(_), _) => self.clone(),
+            (None, _) => other.clone(),
+        }
+    }
+}
@@ -23,4 +23,29 @@ impl Operational {
             impl_: sa.impl_.defer(),
         }
     }
+
+    /// Flatten a `Stream` of collections into a `Stream` that fires
+    /// with each element in a transaction of its own.
+    ///
+    /// The elements fire in order after the transaction in which the
+    /// collection fired, all from a single callback registered with
+    /// [`SodiumCtx::post`][crate::SodiumCtx::post] as the collection
+    /// propagates at the end of that transaction. So they fire after
+    /// any post callbacks registered directly within the transaction.
+    /// If the input fires again from one of those callbacks, or from
+    /// the transactions of the elements themselves, its elements are
+    /// queued behind the ones still waiting, so the order of the input
+    /// is always kept.
+    ///
+    /// Elements still waiting when the returned stream is freed are
+    /// dropped.
+    pub fn split_each<A, COLLECTION>(s: &Stream<COLLECTION>) -> Stream<A>
+    where
+        A: Clone + Send + Sync + 'static,
+        COLLECTION: IntoIterator<Item = A> + Clone + Send + 'static,
+    {
+        Stream {
+            impl_: s.impl_.split_each(),
+        }
+    }
 }
@@ -0,0 +1,309 @@
+use crate::impl_::stream_sink::{RecordingFn, RecordingHook};
+use crate::CellSink;
+use crate::SodiumCtx;
+use crate::StreamSink;
+
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+
+// The log has one line per value sent:
+//
+//     <transaction> <sink name> <encoded value>
+//
+// where values sent with the same transaction number on consecutive
+// lines were sent in the same transaction. Backslashes, newlines and
+// carriage returns in the encoded value are escaped so that each entry
+// stays on one line.
+
+fn escape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+fn unescape(s: &str) -> Option<String> {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next()? {
+                '\\' => result.push('\\'),
+                'n' => result.push('\n'),
+                'r' => result.push('\r'),
+                _ => return None,
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    Some(result)
+}
+
+fn check_name(name: &str) {
+    assert!(
+        !name.is_empty() && !name.contains(char::is_whitespace),
+        "sink name {:?} must be non-empty and contain no whitespace",
+        name
+    );
+}
+
+struct Log {
+    out: Box<dyn Write + Send>,
+    error: Option<io::Error>,
+}
+
+/// Records the values sent to named [`StreamSink`]s and
+/// [`CellSink`]s, along with the transaction each was sent in, as a
+/// line-based log that a [`Replayer`] can feed back through the same
+/// sinks.
+///
+/// Each value is written with the encoder given when its sink was
+/// added, as soon as it is sent. Encoded values may contain any
+/// characters, and are escaped to keep each entry on one line.
+/// Recording continues until [`stop`][Recorder::stop] is called.
+///
+/// Only available with the `recording` feature.
+pub struct Recorder {
+    sodium_ctx: SodiumCtx,
+    log: Arc<Mutex<Log>>,
+    detach: Mutex<Vec<Box<dyn FnOnce() + Send>>>,
+}
+
+impl Recorder {
+    /// Create a `Recorder` that writes its log to `out`.
+    pub fn new<W: Write + Send + 'static>(sodium_ctx: &SodiumCtx, out: W) -> Recorder {
+        Recorder {
+            sodium_ctx: sodium_ctx.clone(),
+            log: Arc::new(Mutex::new(Log {
+                out: Box::new(out),
+                error: None,
+            })),
+            detach: Mutex::new(Vec::new()),
+        }
+    }
+
+    // Set a hook in the given slot that writes the values sent, and
+    // remember how to clear it again.
+    fn attach<A, ENC>(&self, name: &str, slot: RecordingHook<A>, encode: ENC)
+    where
+        A: 'static,
+        ENC: Fn(&A) -> String + Send + Sync + 'static,
+    {
+        check_name(name);
+        let sodium_ctx = self.sodium_ctx.clone();
+        let log = self.log.clone();
+        let name = name.to_string();
+        let hook: RecordingFn<A> = Arc::new(move |a: &A| {
+            let line = format!(
+                "{} {} {}\n",
+                sodium_ctx.impl_.transaction_id(),
+                name,
+                escape(&encode(a))
+            );
+            let mut log = log.lock().unwrap();
+            if log.error.is_none() {
+                if let Err(err) = log.out.write_all(line.as_bytes()) {
+                    log.error = Some(err);
+                }
+            }
+        });
+        *slot.write().unwrap() = Some(hook.clone());
+        self.detach.lock().unwrap().push(Box::new(move || {
+            let mut slot = slot.write().unwrap();
+            let mut is_ours = false;
+            if let Some(hook2) = &*slot {
+                is_ours = Arc::ptr_eq(hook2, &hook);
+            }
+            // Leave the hook of a recorder that took the sink over.
+            if is_ours {
+                *slot = None;
+            }
+        }));
+    }
+
+    /// Record the values sent to a `StreamSink` under the given name,
+    /// encoded with `encode`.
+    ///
+    /// # Panics
+    ///
+    /// If the name is empty or contains whitespace.
+    pub fn record_stream_sink<A, ENC>(&self, name: &str, sink: &StreamSink<A>, encode: ENC)
+    where
+        A: Clone + Send + 'static,
+        ENC: Fn(&A) -> String + Send + Sync + 'static,
+    {
+        self.attach(name, sink.impl_.recording_hook(), encode);
+    }
+
+    /// Record the values sent to a `CellSink` under the given name,
+    /// encoded with `encode`.
+    ///
+    /// # Panics
+    ///
+    /// If the name is empty or contains whitespace.
+    pub fn record_cell_sink<A, ENC>(&self, name: &str, sink: &CellSink<A>, encode: ENC)
+    where
+        A: Clone + Send + 'static,
+        ENC: Fn(&A) -> String + Send + Sync + 'static,
+    {
+        self.attach(name, sink.impl_.recording_hook(), encode);
+    }
+
+    /// Stop recording, detaching this recorder from all the sinks it
+    /// records. Sinks added afterwards are recorded again.
+    pub fn stop(&self) {
+        let detach = std::mem::take(&mut *self.detach.lock().unwrap());
+        for detach in detach {
+            detach();
+        }
+    }
+
+    /// Flush the log, returning the first error met while writing it,
+    /// if any.
+    pub fn flush(&self) -> io::Result<()> {
+        let mut log = self.log.lock().unwrap();
+        if let Some(err) = log.error.take() {
+            return Err(err);
+        }
+        log.out.flush()
+    }
+}
+
+type SendThunk = Box<dyn FnOnce()>;
+
+type Decoder = Box<dyn Fn(&str) -> Option<SendThunk>>;
+
+/// Feeds a log written by a [`Recorder`] back through named sinks,
+/// sending the values of each recorded transaction together in one
+/// transaction.
+///
+/// Only available with the `recording` feature.
+pub struct Replayer {
+    sodium_ctx: SodiumCtx,
+    sinks: HashMap<String, Decoder>,
+}
+
+impl Replayer {
+    /// Create a `Replayer` with no sinks.
+    pub fn new(sodium_ctx: &SodiumCtx) -> Replayer {
+        Replayer {
+            sodium_ctx: sodium_ctx.clone(),
+            sinks: HashMap::new(),
+        }
+    }
+
+    /// Send the values recorded under the given name to a
+    /// `StreamSink`, decoded with `decode`.
+    ///
+    /// # Panics
+    ///
+    /// If the name is empty or contains whitespace.
+    pub fn replay_stream_sink<A, DEC>(&mut self, name: &str, sink: &StreamSink<A>, decode: DEC)
+    where
+        A: Clone + Send + 'static,
+        DEC: Fn(&str) -> Option<A> + 'static,
+    {
+        check_name(name);
+        let sink = sink.clone();
+        self.sinks.insert(
+            name.to_string(),
+            Box::new(move |s: &str| {
+                let a = decode(s)?;
+                let sink = sink.clone();
+                Some(Box::new(move || sink.send(a)) as SendThunk)
+            }),
+        );
+    }
+
+    /// Send the values recorded under the given name to a `CellSink`,
+    /// decoded with `decode`.
+    ///
+    /// # Panics
+    ///
+    /// If the name is empty or contains whitespace.
+    pub fn replay_cell_sink<A, DEC>(&mut self, name: &str, sink: &CellSink<A>, decode: DEC)
+    where
+        A: Clone + Send + 'static,
+        DEC: Fn(&str) -> Option<A> + 'static,
+    {
+        check_name(name);
+        let sink = sink.clone();
+        self.sinks.insert(
+            name.to_string(),
+            Box::new(move |s: &str| {
+                let a = decode(s)?;
+                let sink = sink.clone();
+                Some(Box::new(move || sink.send(a)) as SendThunk)
+            }),
+        );
+    }
+
+    /// Replay a log, one recorded transaction at a time.
+    ///
+    /// The values of each recorded transaction are sent in one
+    /// transaction, in the order they were recorded, so a sink sent
+    /// more than one value coalesces them or keeps the last just as it
+    /// did when recorded.
+    ///
+    /// Each transaction is decoded in full before any of it is sent,
+    /// so an error stops the replay at a transaction boundary. An
+    /// error of kind `InvalidData` is returned for a malformed line, a
+    /// sink name that was not added, or a value that doesn't decode.
+    pub fn replay<R: BufRead>(&self, input: R) -> io::Result<()> {
+        let mut transaction: Option<(String, Vec<SendThunk>)> = None;
+        for (i, line) in input.lines().enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = |msg: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", i + 1, msg),
+                )
+            };
+            let mut parts = line.splitn(3, ' ');
+            let (tx, name, value) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(tx), Some(name), Some(value)) => (tx, name, value),
+                _ => return Err(invalid("malformed entry")),
+            };
+            let decoder = self
+                .sinks
+                .get(name)
+                .ok_or_else(|| invalid(&format!("unknown sink {:?}", name)))?;
+            let send = unescape(value)
+                .and_then(|value| decoder(&value))
+                .ok_or_else(|| invalid(&format!("undecodable value for sink {:?}", name)))?;
+            match transaction {
+                Some((ref tx2, ref mut sends)) if tx2 == tx => sends.push(send),
+                _ => {
+                    if let Some((_, sends)) = transaction.take() {
+                        self.send_all(sends);
+                    }
+                    transaction = Some((tx.to_string(), vec![send]));
+                }
+            }
+        }
+        if let Some((_, sends)) = transaction {
+            self.send_all(sends);
+        }
+        Ok(())
+    }
+
+    fn send_all(&self, sends: Vec<SendThunk>) {
+        self.sodium_ctx.transaction(|| {
+            for send in sends {
+                send();
+            }
+        });
+    }
+}
@@ -1,7 +1,10 @@
+use crate::impl_::router::OrderedRouter as OrderedRouterImpl;
 use crate::impl_::router::Router as RouterImpl;
+use crate::impl_::router::RouterChange;
 use crate::SodiumCtx;
 use crate::Stream;
 use std::hash::Hash;
+use std::ops::RangeBounds;
 
 /// Create a new Router that routes event items of type `A` to zero or
 /// more [`Stream`]s of type `K` according to a given selector
@@ -10,6 +13,14 @@ pub struct Router<A, K> {
     impl_: RouterImpl<A, K>,
 }
 
+impl<A, K> Clone for Router<A, K> {
+    fn clone(&self) -> Self {
+        Router {
+            impl_: self.impl_.clone(),
+        }
+    }
+}
+
 impl<A, K> Router<A, K> {
     /// Create a new `Router` from the given input stream and selector
     /// function.
@@ -27,6 +38,26 @@ impl<A, K> Router<A, K> {
         }
     }
 
+    /// Create a new `Router` whose selector function routes each event
+    /// value to at most one key.
+    ///
+    /// This behaves like [`new`][Router::new] with a selector that
+    /// returns a `Vec` of zero or one keys, but without allocating one
+    /// for every event.
+    pub fn new_single(
+        sodium_ctx: &SodiumCtx,
+        in_stream: &Stream<A>,
+        selector: impl Fn(&A) -> Option<K> + Send + Sync + 'static,
+    ) -> Router<A, K>
+    where
+        A: Clone + Send + 'static,
+        K: Send + Sync + Eq + Hash + 'static,
+    {
+        Router {
+            impl_: RouterImpl::new_single(&sodium_ctx.impl_, &in_stream.impl_, selector),
+        }
+    }
+
     /// Create a Stream that is subscribed to event values that the
     /// selector function routes to the given `K` value.
     pub fn filter_matches(&self, k: &K) -> Stream<A>
@@ -38,4 +69,120 @@ impl<A, K> Router<A, K> {
             impl_: self.impl_.filter_matches(k),
         }
     }
+
+    /// Create a Stream of the event values that were not routed to any
+    /// live [`filter_matches`][Router::filter_matches] stream, either
+    /// because the selector function returned no keys for them or
+    /// because none of their keys had a subscriber.
+    pub fn unmatched(&self) -> Stream<A>
+    where
+        A: Clone + Send + 'static,
+        K: Send + Sync + 'static,
+    {
+        Stream {
+            impl_: self.impl_.unmatched(),
+        }
+    }
+
+    /// The keys that currently have a live
+    /// [`filter_matches`][Router::filter_matches] stream, in an
+    /// unspecified order.
+    pub fn active_keys(&self) -> Vec<K>
+    where
+        K: Clone,
+    {
+        self.impl_.active_keys()
+    }
+
+    /// Create a Stream that fires when a key gains its first
+    /// [`filter_matches`][Router::filter_matches] stream, or when the
+    /// last one is freed.
+    ///
+    /// Each change fires in a transaction of its own, after the
+    /// transaction it was made in. A stream freed by garbage
+    /// collection outside any transaction is reported at the start of
+    /// the next transaction.
+    pub fn changes(&self) -> Stream<RouterChange<K>>
+    where
+        K: Send + 'static,
+    {
+        Stream {
+            impl_: self.impl_.changes(),
+        }
+    }
+}
+
+/// A router whose keys are ordered, so that a subscriber can receive
+/// the event values for a whole range of keys.
+///
+/// Subscriptions to a single key are kept in a `BTreeMap`, and those
+/// to a range in an interval index, so each event only visits the
+/// subscriptions that contain its key. As with [`Router`], the stream
+/// for each range is created on demand and only held weakly, so it is
+/// freed once nothing observes it.
+pub struct OrderedRouter<A, K> {
+    impl_: OrderedRouterImpl<A, K>,
+}
+
+impl<A, K> Clone for OrderedRouter<A, K> {
+    fn clone(&self) -> Self {
+        OrderedRouter {
+            impl_: self.impl_.clone(),
+        }
+    }
+}
+
+impl<A, K> OrderedRouter<A, K> {
+    /// Create a new `OrderedRouter` from the given input stream and a
+    /// selector function giving the key of each event value, if any.
+    pub fn new(
+        sodium_ctx: &SodiumCtx,
+        in_stream: &Stream<A>,
+        selector: impl Fn(&A) -> Option<K> + Send + Sync + 'static,
+    ) -> OrderedRouter<A, K>
+    where
+        A: Clone + Send + 'static,
+        K: Clone + Ord + Send + Sync + 'static,
+    {
+        OrderedRouter {
+            impl_: OrderedRouterImpl::new(&sodium_ctx.impl_, &in_stream.impl_, selector),
+        }
+    }
+
+    /// Create a Stream that is subscribed to event values whose key is
+    /// the given `K` value.
+    pub fn filter_matches(&self, k: &K) -> Stream<A>
+    where
+        A: Clone + Send + 'static,
+        K: Clone + Ord + Send + Sync + 'static,
+    {
+        Stream {
+            impl_: self.impl_.filter_matches(k),
+        }
+    }
+
+    /// Create a Stream that is subscribed to event values whose key is
+    /// in the given range.
+    pub fn filter_range<R: RangeBounds<K>>(&self, range: R) -> Stream<A>
+    where
+        A: Clone + Send + 'static,
+        K: Clone + Ord + Send + Sync + 'static,
+    {
+        Stream {
+            impl_: self.impl_.filter_range(range),
+        }
+    }
+}
+
+impl<A> OrderedRouter<A, String> {
+    /// Create a Stream that is subscribed to event values whose key
+    /// starts with the given prefix.
+    pub fn filter_prefix(&self, prefix: &str) -> Stream<A>
+    where
+        A: Clone + Send + 'static,
+    {
+        Stream {
+            impl_: self.impl_.filter_prefix(prefix),
+        }
+    }
 }
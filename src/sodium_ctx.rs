@@ -1,12 +1,16 @@
+use crate::impl_::sodium_ctx::DependencyValidation;
 use crate::impl_::sodium_ctx::SodiumCtx as SodiumCtxImpl;
 use crate::Cell;
 use crate::CellLoop;
 use crate::CellSink;
+use crate::History;
 use crate::Router;
+use crate::StateStore;
 use crate::Stream;
 use crate::StreamLoop;
 use crate::StreamSink;
 use crate::Transaction;
+use std::collections::BTreeMap;
 use std::hash::Hash;
 
 /// A context object representing a specific instance of a Sodium
@@ -96,6 +100,18 @@ impl SodiumCtx {
         self.impl_.post(k);
     }
 
+    /// Set how a [`Cell::sample`] of a cell that was not declared as a
+    /// dependency of the currently updating node is reported.
+    ///
+    /// Cells sampled inside the functions passed to [`Stream::map`]
+    /// and friends must be declared through `lambda1`, `lambda2`,
+    /// etc. or the garbage collector may free them or fail to
+    /// collect cycles. This check is intended for debugging and is
+    /// [`DependencyValidation::Off`] by default.
+    pub fn set_dependency_validation(&self, dependency_validation: DependencyValidation) {
+        self.impl_.set_dependency_validation(dependency_validation);
+    }
+
     /// Create a new [`Router`] in this context.
     pub fn new_router<A, K>(
         &self,
@@ -108,4 +124,54 @@ impl SodiumCtx {
     {
         Router::new(self, in_stream, selector)
     }
+
+    /// The [`StateStore`] holding this context's named accumulators.
+    pub fn state_store(&self) -> StateStore {
+        StateStore {
+            impl_: self.impl_.state_store(),
+        }
+    }
+
+    /// Encode the current value of every live named accumulator with
+    /// its [`Codec`][crate::Codec], by name.
+    ///
+    /// See [`StateStore::snapshot`].
+    pub fn snapshot_state(&self) -> BTreeMap<String, String> {
+        self.state_store().snapshot()
+    }
+
+    /// Re-seed the named accumulators of a graph that is about to be
+    /// rebuilt with the values from an earlier
+    /// [`snapshot_state`][SodiumCtx::snapshot_state].
+    ///
+    /// This must be called before the graph is built. See
+    /// [`StateStore::restore`].
+    pub fn restore_state(&self, state: BTreeMap<String, String>) {
+        self.state_store().restore(state);
+    }
+
+    /// The id of the transaction in progress, or of the last one if
+    /// none is.
+    ///
+    /// Ids start from 1 and increase by one with each outermost
+    /// transaction, including those started implicitly by `send` and
+    /// `listen`.
+    pub fn transaction_id(&self) -> u64 {
+        self.impl_.transaction_id()
+    }
+
+    /// Keep the values of the cells tracked with
+    /// [`Cell::track_history`] for the last `capacity` transactions,
+    /// dropping any older ones already kept. A capacity of 0, the
+    /// default, disables history.
+    pub fn set_history_capacity(&self, capacity: usize) {
+        self.impl_.history().set_capacity(capacity);
+    }
+
+    /// The [`History`] of this context's tracked cells.
+    pub fn history(&self) -> History {
+        History {
+            impl_: self.impl_.history(),
+        }
+    }
 }
@@ -0,0 +1,110 @@
+use crate::impl_::state_store::StateStore as StateStoreImpl;
+use crate::Cell;
+
+use log::warn;
+use std::collections::BTreeMap;
+
+/// Converts the state of a named accumulator to and from a string, so
+/// that it can be saved by [`SodiumCtx::snapshot_state`] and brought
+/// back by [`SodiumCtx::restore_state`].
+///
+/// Implemented for the primitive numeric types, `bool`, `char` and
+/// `String` through their `Display` and `FromStr` implementations.
+///
+/// [`SodiumCtx::snapshot_state`]: crate::SodiumCtx::snapshot_state
+/// [`SodiumCtx::restore_state`]: crate::SodiumCtx::restore_state
+pub trait Codec: Sized {
+    /// Encode a value.
+    fn encode(&self) -> String;
+
+    /// Decode a value written by [`encode`][Codec::encode], returning
+    /// `None` if it is not valid.
+    fn decode(s: &str) -> Option<Self>;
+}
+
+macro_rules! impl_codec_via_from_str {
+    ($($t:ty),+) => {
+        $(
+            impl Codec for $t {
+                fn encode(&self) -> String {
+                    self.to_string()
+                }
+
+                fn decode(s: &str) -> Option<Self> {
+                    s.parse().ok()
+                }
+            }
+        )+
+    };
+}
+
+impl_codec_via_from_str!(i8, i16, i32, i64, i128, isize);
+impl_codec_via_from_str!(u8, u16, u32, u64, u128, usize);
+impl_codec_via_from_str!(f32, f64, bool, char, String);
+
+/// The named accumulators of a [`SodiumCtx`][crate::SodiumCtx].
+///
+/// Cells created with [`Stream::accum_named`], [`Stream::hold_named`]
+/// and the state of [`Stream::collect_named`] register here under
+/// their name. Each is held weakly, and is dropped from the store once
+/// freed.
+///
+/// [`Stream::accum_named`]: crate::Stream::accum_named
+/// [`Stream::hold_named`]: crate::Stream::hold_named
+/// [`Stream::collect_named`]: crate::Stream::collect_named
+#[derive(Clone)]
+pub struct StateStore {
+    pub impl_: StateStoreImpl,
+}
+
+impl StateStore {
+    /// The encoded current value of every live named accumulator, by
+    /// name.
+    ///
+    /// This samples each cell, so it should be called outside of a
+    /// transaction to see the values it has settled on.
+    pub fn snapshot(&self) -> BTreeMap<String, String> {
+        self.impl_.snapshot()
+    }
+
+    /// Set the values that named accumulators created from now on
+    /// start from, replacing any set by an earlier call.
+    ///
+    /// Each value is used by the first accumulator created with its
+    /// name, and then discarded. A value that doesn't decode is logged
+    /// and the accumulator's given initial value used instead.
+    pub fn restore(&self, state: BTreeMap<String, String>) {
+        self.impl_.restore(state);
+    }
+
+    /// The names of the registered accumulators, in order. This may
+    /// include some that have been freed since the last
+    /// [`snapshot`][StateStore::snapshot].
+    pub fn names(&self) -> Vec<String> {
+        self.impl_.names()
+    }
+
+    pub(crate) fn restored_or<S: Codec>(&self, name: &str, init: S) -> S {
+        match self.impl_.take_restored(name) {
+            Some(s) => S::decode(&s).unwrap_or_else(|| {
+                warn!(
+                    "could not decode restored state {:?} for {:?}, using its initial value",
+                    s, name
+                );
+                init
+            }),
+            None => init,
+        }
+    }
+
+    pub(crate) fn register<S>(&self, name: &str, cell: &Cell<S>)
+    where
+        S: Codec + Clone + Send + 'static,
+    {
+        let cell = cell.downgrade();
+        self.impl_.register(
+            name,
+            Box::new(move || cell.upgrade().map(|cell| cell.sample().encode())),
+        );
+    }
+}
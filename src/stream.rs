@@ -1,11 +1,24 @@
 use crate::cell::Cell;
+use crate::cell::CellTuple;
 use crate::impl_::dep::Dep;
-use crate::impl_::lambda::{lambda1, lambda2};
+use crate::impl_::dep::Trace;
+use crate::impl_::lambda::{lambda1, lambda1_deps};
+use crate::impl_::lambda::{lambda2_deps, lambda3_deps, lambda4_deps, lambda5_deps, lambda6_deps};
 use crate::impl_::lambda::{IsLambda1, IsLambda2, IsLambda3, IsLambda4, IsLambda5, IsLambda6};
 use crate::impl_::stream::Stream as StreamImpl;
+use crate::impl_::stream::WeakStream as WeakStreamImpl;
+use crate::lambda;
 use crate::listener::Listener;
+use crate::monoid::{Max, Min, Monoid};
 use crate::sodium_ctx::SodiumCtx;
+use crate::state_store::Codec;
 use crate::Lazy;
+use crate::These;
+
+use std::borrow::Borrow;
+use std::collections::VecDeque;
+use std::hash::Hash;
+use std::sync::Mutex;
 
 /// Represents a stream of discrete events/firings containing values
 /// of type `A`.
@@ -17,6 +30,15 @@ pub struct Stream<A> {
     pub impl_: StreamImpl<A>,
 }
 
+/// A weak reference to a [`Stream`] that does not keep the stream,
+/// or the part of the FRP graph it depends on, alive.
+///
+/// Use [`upgrade`][WeakStream::upgrade] to get the [`Stream`] back
+/// while it is still alive.
+pub struct WeakStream<A> {
+    pub impl_: WeakStreamImpl<A>,
+}
+
 impl<A> Clone for Stream<A> {
     fn clone(&self) -> Self {
         Stream {
@@ -25,13 +47,34 @@ impl<A> Clone for Stream<A> {
     }
 }
 
+impl<A> Clone for WeakStream<A> {
+    fn clone(&self) -> Self {
+        WeakStream {
+            impl_: self.impl_.clone(),
+        }
+    }
+}
+
+impl<A> Trace for Stream<A> {
+    fn trace(&self, deps: &mut Vec<Dep>) {
+        deps.push(self.impl_.to_dep());
+    }
+}
+
+impl<A> WeakStream<A> {
+    /// Attempt to get a strong reference to the [`Stream`], returning
+    /// `None` if it has already been garbage-collected.
+    pub fn upgrade(&self) -> Option<Stream<A>> {
+        self.impl_.upgrade().map(|impl_| Stream { impl_ })
+    }
+}
+
 impl<A: Clone + Send + 'static> Stream<Option<A>> {
     /// Return a `Stream` that only outputs events that have present
     /// values, removing the `Option` wrapper and discarding empty
     /// values.
     pub fn filter_option(&self) -> Stream<A> {
-        self.filter(|a: &Option<A>| a.is_some())
-            .map(|a: &Option<A>| a.clone().unwrap())
+        self.filter_map(|a: &Option<A>| a.clone())
     }
 }
 
@@ -43,6 +86,12 @@ impl<
 {
     /// Flatten a `Stream` of a collection of `A` into a `Stream` of
     /// single `A`s.
+    ///
+    /// Each element is sent in a transaction of its own, after the
+    /// transaction in which the collection fired. See
+    /// [`Operational::split_each`][crate::Operational::split_each] for
+    /// a variant that also guarantees the elements keep their order
+    /// when the input fires again before they have all been sent.
     pub fn split(&self) -> Stream<A> {
         Stream {
             impl_: self.impl_.split(),
@@ -64,6 +113,17 @@ impl<A: Clone + Send + 'static> Stream<A> {
         self.impl_.to_dep()
     }
 
+    /// Create a [`WeakStream`] reference to this `Stream`.
+    ///
+    /// A weak reference does not keep the stream alive, so it can be
+    /// held by I/O code or UI widgets without keeping the FRP graph
+    /// from being garbage-collected.
+    pub fn downgrade(&self) -> WeakStream<A> {
+        WeakStream {
+            impl_: StreamImpl::downgrade(&self.impl_),
+        }
+    }
+
     /// Return a stream whose events are the result of the combination
     /// of the event value and the current value of the cell using the
     /// specified function.
@@ -108,17 +168,10 @@ impl<A: Clone + Send + 'static> Stream<A> {
         cc: &Cell<C>,
         mut f: FN,
     ) -> Stream<D> {
-        let mut deps: Vec<Dep>;
-        if let Some(deps2) = f.deps_op() {
-            deps = deps2.clone();
-        } else {
-            deps = Vec::new();
-        }
-        let cc = cc.clone();
-        deps.push(cc.to_dep());
+        let f_deps = lambda3_deps(&f);
         self.snapshot(
             cb,
-            lambda2(move |a: &A, b: &B| f.call(a, b, &cc.sample()), deps),
+            lambda!([cc, f_deps] move |a: &A, b: &B| f.call(a, b, &cc.sample())),
         )
     }
 
@@ -137,22 +190,12 @@ impl<A: Clone + Send + 'static> Stream<A> {
         cd: &Cell<D>,
         mut f: FN,
     ) -> Stream<E> {
-        let mut deps: Vec<Dep>;
-        if let Some(deps2) = f.deps_op() {
-            deps = deps2.clone();
-        } else {
-            deps = Vec::new();
-        }
-        let cc = cc.clone();
-        let cd = cd.clone();
-        deps.push(cc.to_dep());
-        deps.push(cd.to_dep());
+        let f_deps = lambda4_deps(&f);
         self.snapshot(
             cb,
-            lambda2(
-                move |a: &A, b: &B| f.call(a, b, &cc.sample(), &cd.sample()),
-                deps,
-            ),
+            lambda!([cc, cd, f_deps] move |a: &A, b: &B| {
+                f.call(a, b, &cc.sample(), &cd.sample())
+            }),
         )
     }
 
@@ -173,24 +216,12 @@ impl<A: Clone + Send + 'static> Stream<A> {
         ce: &Cell<E>,
         mut f: FN,
     ) -> Stream<F> {
-        let mut deps: Vec<Dep>;
-        if let Some(deps2) = f.deps_op() {
-            deps = deps2.clone();
-        } else {
-            deps = Vec::new();
-        }
-        let cc = cc.clone();
-        let cd = cd.clone();
-        let ce = ce.clone();
-        deps.push(cc.to_dep());
-        deps.push(cd.to_dep());
-        deps.push(ce.to_dep());
+        let f_deps = lambda5_deps(&f);
         self.snapshot(
             cb,
-            lambda2(
-                move |a: &A, b: &B| f.call(a, b, &cc.sample(), &cd.sample(), &ce.sample()),
-                deps,
-            ),
+            lambda!([cc, cd, ce, f_deps] move |a: &A, b: &B| {
+                f.call(a, b, &cc.sample(), &cd.sample(), &ce.sample())
+            }),
         )
     }
 
@@ -213,31 +244,32 @@ impl<A: Clone + Send + 'static> Stream<A> {
         cf: &Cell<F>,
         mut f: FN,
     ) -> Stream<G> {
-        let mut deps: Vec<Dep>;
-        if let Some(deps2) = f.deps_op() {
-            deps = deps2.clone();
-        } else {
-            deps = Vec::new();
-        }
-        let cc = cc.clone();
-        let cd = cd.clone();
-        let ce = ce.clone();
-        let cf = cf.clone();
-        deps.push(cc.to_dep());
-        deps.push(cd.to_dep());
-        deps.push(ce.to_dep());
-        deps.push(cf.to_dep());
+        let f_deps = lambda6_deps(&f);
         self.snapshot(
             cb,
-            lambda2(
-                move |a: &A, b: &B| {
-                    f.call(a, b, &cc.sample(), &cd.sample(), &ce.sample(), &cf.sample())
-                },
-                deps,
-            ),
+            lambda!([cc, cd, ce, cf, f_deps] move |a: &A, b: &B| {
+                f.call(a, b, &cc.sample(), &cd.sample(), &ce.sample(), &cf.sample())
+            }),
         )
     }
 
+    /// A variant of [`snapshot`][Stream::snapshot] that captures the
+    /// values of a tuple of up to twelve cells.
+    pub fn snapshot_tuple<
+        T: CellTuple,
+        C: Send + Clone + 'static,
+        FN: IsLambda2<A, T::Values, C> + Send + Sync + 'static,
+    >(
+        &self,
+        cells: T,
+        mut f: FN,
+    ) -> Stream<C> {
+        let mut deps = lambda2_deps(&f);
+        let (cells_deps, mut sample) = cells.sampler();
+        deps.extend(cells_deps);
+        self.map(lambda1(move |a: &A| f.call(a, &sample()), deps))
+    }
+
     /// Transform this `Stream`'s event values with the supplied
     /// function.
     ///
@@ -269,6 +301,68 @@ impl<A: Clone + Send + 'static> Stream<A> {
         }
     }
 
+    /// Return a `Stream` that outputs the present results of applying
+    /// the function to each event, discarding events for which it
+    /// returns `None`.
+    ///
+    /// This is equivalent to `map(f).filter_option()`, but uses a
+    /// single node and clones nothing.
+    pub fn filter_map<
+        B: Send + Clone + 'static,
+        FN: IsLambda1<A, Option<B>> + Send + Sync + 'static,
+    >(
+        &self,
+        f: FN,
+    ) -> Stream<B> {
+        Stream {
+            impl_: self.impl_.filter_map(f),
+        }
+    }
+
+    /// Split this `Stream` in two: the first returned stream outputs
+    /// the events for which the predicate returns `true`, and the
+    /// second those for which it returns `false`.
+    ///
+    /// The predicate is evaluated only once per event, in a node
+    /// shared by both outputs. Each output is a node of its own that
+    /// only receives what the shared node sends it, so `partition`
+    /// creates three nodes in all.
+    pub fn partition<PRED: IsLambda1<A, bool> + Send + Sync + 'static>(
+        &self,
+        pred: PRED,
+    ) -> (Stream<A>, Stream<A>) {
+        let (s_true, s_false) = self.impl_.partition(pred);
+        (Stream { impl_: s_true }, Stream { impl_: s_false })
+    }
+
+    /// Split this `Stream` into a substream per key, as given by the
+    /// key function.
+    ///
+    /// The returned stream fires with the key and its substream the
+    /// first time an event with that key is seen, and the substream
+    /// then receives every event with that key. Unlike a [`Router`],
+    /// this doesn't require the keys to be known in advance.
+    ///
+    /// Nothing can observe a substream in the transaction in which it
+    /// is created, so the event that introduced its key fires on it in
+    /// a transaction of its own straight after, as with
+    /// [`Operational::defer`]. The substreams are only held weakly:
+    /// once one is freed, the next event with its key starts a new
+    /// one.
+    ///
+    /// [`Router`]: crate::Router
+    /// [`Operational::defer`]: crate::Operational::defer
+    pub fn group_by<K, FN>(&self, key_fn: FN) -> Stream<(K, Stream<A>)>
+    where
+        K: Clone + Eq + Hash + Send + Sync + 'static,
+        FN: IsLambda1<A, K> + Send + Sync + 'static,
+    {
+        Stream {
+            impl_: self.impl_.group_by(key_fn),
+        }
+        .map(|(k, sub): &(K, StreamImpl<A>)| (k.clone(), Stream { impl_: sub.clone() }))
+    }
+
     /// Variant of [`merge`][Stream::merge] that merges two streams.
     ///
     /// In the case where two events are simultaneous (both in the
@@ -304,6 +398,65 @@ impl<A: Clone + Send + 'static> Stream<A> {
         }
     }
 
+    /// Merge two streams of different types into one, tagging each
+    /// event with the side it came from.
+    ///
+    /// When both streams fire in the same transaction the returned
+    /// stream fires once with [`These::Both`], so the simultaneity is
+    /// kept rather than lost as it would be by mapping both sides to
+    /// a common type and using [`or_else`][Stream::or_else].
+    pub fn merge_with<B: Clone + Send + 'static>(&self, s2: &Stream<B>) -> Stream<These<A, B>> {
+        Stream {
+            impl_: self.impl_.merge_with(&s2.impl_),
+        }
+    }
+
+    /// Variant of [`merge_all`][Stream::merge_all] that keeps only
+    /// the leftmost of any simultaneous events.
+    ///
+    /// This is equivalent to folding the streams with
+    /// [`or_else`][Stream::or_else], but uses a single node.
+    pub fn or_else_all<I>(sodium_ctx: &SodiumCtx, streams: I) -> Stream<A>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Stream<A>>,
+    {
+        Stream {
+            impl_: StreamImpl::or_else_all(&sodium_ctx.impl_, Stream::collect_impls(streams)),
+        }
+    }
+
+    /// Merge any number of streams of the same type into one.
+    ///
+    /// Simultaneous events are combined left to right with the
+    /// combining function, exactly as if the streams had been folded
+    /// with [`merge`][Stream::merge]. Unlike the folded form, the
+    /// returned stream is a single node with one dependency per input
+    /// stream, rather than a chain of `n - 1` merge nodes.
+    ///
+    /// Merging no streams gives a stream that never fires.
+    pub fn merge_all<I, FN>(sodium_ctx: &SodiumCtx, streams: I, f: FN) -> Stream<A>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Stream<A>>,
+        FN: IsLambda2<A, A, A> + Send + Sync + 'static,
+    {
+        Stream {
+            impl_: StreamImpl::merge_all(&sodium_ctx.impl_, Stream::collect_impls(streams), f),
+        }
+    }
+
+    fn collect_impls<I>(streams: I) -> Vec<StreamImpl<A>>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Stream<A>>,
+    {
+        streams
+            .into_iter()
+            .map(|s| s.borrow().impl_.clone())
+            .collect()
+    }
+
     /// Returns a cell with the specified initial value, which is
     /// updated by this stream's event values.
     pub fn hold(&self, a: A) -> Cell<A> {
@@ -312,6 +465,27 @@ impl<A: Clone + Send + 'static> Stream<A> {
         }
     }
 
+    /// A variant of [`hold`][Stream::hold] whose value is registered
+    /// under the given name in the context's
+    /// [`StateStore`][crate::StateStore].
+    ///
+    /// If a value was restored for the name with
+    /// [`SodiumCtx::restore_state`], the cell starts from it instead of
+    /// `a`. Names should be unique within a context: a later
+    /// registration replaces an earlier one.
+    pub fn hold_named(&self, name: &str, a: A) -> Cell<A>
+    where
+        A: Codec,
+    {
+        let sodium_ctx = SodiumCtx {
+            impl_: self.impl_.sodium_ctx(),
+        };
+        let state_store = sodium_ctx.state_store();
+        let cell = self.hold(state_store.restored_or(name, a));
+        state_store.register(name, &cell);
+        cell
+    }
+
     /// A variant of [`hold`][Stream::hold] that uses an initial value
     /// returned by [`Cell::sample_lazy`].
     pub fn hold_lazy(&self, a: Lazy<A>) -> Cell<A> {
@@ -328,6 +502,137 @@ impl<A: Clone + Send + 'static> Stream<A> {
         self.filter(lambda1(move |_: &A| cpred.sample(), vec![cpred_dep]))
     }
 
+    /// Return a stream that fires with the `(previous, current)` pair
+    /// of events for every event after the first.
+    pub fn pairwise(&self) -> Stream<(A, A)> {
+        let previous = self.map(|a: &A| Some(a.clone())).hold(None);
+        self.snapshot(&previous, |a: &A, previous_op: &Option<A>| {
+            previous_op
+                .as_ref()
+                .map(|previous| (previous.clone(), a.clone()))
+        })
+        .filter_option()
+    }
+
+    /// Return a stream that collects the events of this stream into
+    /// groups of `n`, firing with each group once it is complete.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    pub fn chunks(&self, n: usize) -> Stream<Vec<A>> {
+        assert!(n > 0, "chunk size must be non-zero");
+        let chunk: Mutex<Vec<A>> = Mutex::new(Vec::with_capacity(n));
+        self.filter_map(move |a: &A| {
+            let mut chunk = chunk.lock().unwrap();
+            chunk.push(a.clone());
+            if chunk.len() == n {
+                Some(std::mem::replace(&mut *chunk, Vec::with_capacity(n)))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Return a stream that fires with the last `n` events of this
+    /// stream, oldest first, for every event from the `n`th onwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    pub fn sliding(&self, n: usize) -> Stream<Vec<A>> {
+        assert!(n > 0, "window size must be non-zero");
+        let window: Mutex<VecDeque<A>> = Mutex::new(VecDeque::with_capacity(n));
+        self.filter_map(move |a: &A| {
+            let mut window = window.lock().unwrap();
+            if window.len() == n {
+                window.pop_front();
+            }
+            window.push_back(a.clone());
+            if window.len() == n {
+                Some(window.iter().cloned().collect())
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Return a stream that holds back the events of this stream for
+    /// as long as `closed` is `true`, and fires with all of them as
+    /// one `Vec` in the transaction where `closed` becomes `false`.
+    ///
+    /// While `closed` is `false`, each event is passed through as a
+    /// `Vec` of one. An update to `closed` takes effect in the same
+    /// transaction, so an event that is simultaneous with the gate
+    /// opening is flushed together with the buffered ones, and an
+    /// event that is simultaneous with it closing is buffered.
+    pub fn buffer_while(&self, closed: &Cell<bool>) -> Stream<Vec<A>> {
+        Stream {
+            impl_: self.impl_.buffer_while(&closed.impl_),
+        }
+    }
+
+    /// Return a stream that drops every event that is equal to the
+    /// event before it.
+    ///
+    /// The first event is always output.
+    pub fn dedup(&self) -> Stream<A>
+    where
+        A: PartialEq,
+    {
+        self.calm_lazy(None, |a: &A, b: &A| a == b)
+    }
+
+    /// Return a stream that drops every event whose key, as computed
+    /// by `f`, is equal to the key of the event before it.
+    ///
+    /// The first event is always output.
+    pub fn dedup_by_key<K, FN>(&self, mut f: FN) -> Stream<A>
+    where
+        K: PartialEq + Send + 'static,
+        FN: IsLambda1<A, K> + Send + Sync + 'static,
+    {
+        let f_deps = lambda1_deps(&f);
+        let last_key: Mutex<Option<K>> = Mutex::new(None);
+        self.filter(lambda1(
+            move |a: &A| {
+                let key = f.call(a);
+                let mut last_key = last_key.lock().unwrap();
+                let is_new = last_key.as_ref() != Some(&key);
+                *last_key = Some(key);
+                is_new
+            },
+            f_deps,
+        ))
+    }
+
+    // Drop every event for which `eq` returns `true` when compared
+    // with the event before it, or with `init` for the first event.
+    pub(crate) fn calm_lazy<EQ: IsLambda2<A, A, bool> + Send + Sync + 'static>(
+        &self,
+        init: Option<Lazy<A>>,
+        mut eq: EQ,
+    ) -> Stream<A> {
+        let eq_deps = lambda2_deps(&eq);
+        let state: Mutex<(Option<Lazy<A>>, Option<A>)> = Mutex::new((init, None));
+        self.filter(lambda1(
+            move |a: &A| {
+                let mut state = state.lock().unwrap();
+                let (ref mut init_op, ref mut last_op) = *state;
+                let last_op = last_op
+                    .take()
+                    .or_else(|| init_op.take().map(|init| init.run()));
+                let is_new = match last_op {
+                    Some(ref last) => !eq.call(last, a),
+                    None => true,
+                };
+                state.1 = Some(a.clone());
+                is_new
+            },
+            eq_deps,
+        ))
+    }
+
     /// Return a stream that outputs only one value, which is the next
     /// event of the input stream, starting from the transaction in
     /// `once` was invoked.
@@ -337,6 +642,65 @@ impl<A: Clone + Send + 'static> Stream<A> {
         }
     }
 
+    /// Return a stream that outputs only the next `n` events of the
+    /// input stream, starting from the transaction in which `take`
+    /// was invoked.
+    ///
+    /// Like [`once`][Stream::once], the returned stream drops its
+    /// dependency on the input stream after the last event.
+    pub fn take(&self, n: usize) -> Stream<A> {
+        Stream {
+            impl_: self.impl_.take(n),
+        }
+    }
+
+    /// Return a stream that outputs every event of the input stream
+    /// except the next `n`.
+    pub fn skip(&self, n: usize) -> Stream<A> {
+        Stream {
+            impl_: self.impl_.skip(n),
+        }
+    }
+
+    /// Return a stream that outputs the events of the input stream
+    /// for as long as the predicate returns `true`.
+    ///
+    /// The first event for which the predicate returns `false` is not
+    /// output, and the returned stream then drops its dependency on
+    /// the input stream.
+    pub fn take_while<PRED: IsLambda1<A, bool> + Send + Sync + 'static>(
+        &self,
+        pred: PRED,
+    ) -> Stream<A> {
+        Stream {
+            impl_: self.impl_.take_while(pred),
+        }
+    }
+
+    /// Return a stream that drops the events of the input stream for
+    /// as long as the predicate returns `true`, and outputs every
+    /// event from the first one for which it returns `false`.
+    pub fn skip_while<PRED: IsLambda1<A, bool> + Send + Sync + 'static>(
+        &self,
+        pred: PRED,
+    ) -> Stream<A> {
+        Stream {
+            impl_: self.impl_.skip_while(pred),
+        }
+    }
+
+    /// Return a stream that outputs the events of the input stream
+    /// until `s2` fires.
+    ///
+    /// An event that is simultaneous with the first event on `s2` is
+    /// not output. The returned stream then drops its dependencies on
+    /// both streams.
+    pub fn take_until<B: Send + 'static>(&self, s2: &Stream<B>) -> Stream<A> {
+        Stream {
+            impl_: self.impl_.take_until(&s2.impl_),
+        }
+    }
+
     /// Transform an event with a generalized state loop (a Mealy
     /// machine). The function is passed the input and the old state
     /// and returns the new state and output value.
@@ -349,6 +713,32 @@ impl<A: Clone + Send + 'static> Stream<A> {
         self.collect_lazy(Lazy::new(move || init_state.clone()), f)
     }
 
+    /// A variant of [`collect`][Stream::collect] whose state is
+    /// registered under the given name in the context's
+    /// [`StateStore`][crate::StateStore].
+    ///
+    /// As with [`hold_named`][Stream::hold_named], a restored state
+    /// takes the place of `init_state`.
+    pub fn collect_named<B, S, F>(&self, name: &str, init_state: S, f: F) -> Stream<B>
+    where
+        B: Send + Clone + 'static,
+        S: Codec + Send + Clone + 'static,
+        F: IsLambda2<A, S, (B, S)> + Send + Sync + 'static,
+    {
+        let sodium_ctx = SodiumCtx {
+            impl_: self.impl_.sodium_ctx(),
+        };
+        let state_store = sodium_ctx.state_store();
+        let init_state = state_store.restored_or(name, init_state);
+        sodium_ctx.transaction(|| {
+            let state = sodium_ctx.new_cell_loop();
+            let bs = self.snapshot(&state.cell(), f);
+            state.loop_(&bs.map(|bs: &(B, S)| bs.1.clone()).hold(init_state));
+            state_store.register(name, &state.cell());
+            bs.map(|bs: &(B, S)| bs.0.clone())
+        })
+    }
+
     /// A variant of [`collect`][Stream::collect] that takes an
     /// initial state that is returned by [`Cell::sample_lazy`].
     pub fn collect_lazy<B, S, F>(&self, init_state: Lazy<S>, f: F) -> Stream<B>
@@ -378,6 +768,26 @@ impl<A: Clone + Send + 'static> Stream<A> {
         self.accum_lazy(Lazy::new(move || init_state.clone()), f)
     }
 
+    /// A variant of [`accum`][Stream::accum] whose state is registered
+    /// under the given name in the context's
+    /// [`StateStore`][crate::StateStore].
+    ///
+    /// As with [`hold_named`][Stream::hold_named], a restored state
+    /// takes the place of `init_state`.
+    pub fn accum_named<S, F>(&self, name: &str, init_state: S, f: F) -> Cell<S>
+    where
+        S: Codec + Send + Clone + 'static,
+        F: IsLambda2<A, S, S> + Send + Sync + 'static,
+    {
+        let sodium_ctx = SodiumCtx {
+            impl_: self.impl_.sodium_ctx(),
+        };
+        let state_store = sodium_ctx.state_store();
+        let cell = self.accum(state_store.restored_or(name, init_state), f);
+        state_store.register(name, &cell);
+        cell
+    }
+
     /// A variant of [`accum`][Stream::accum] that takes an initial
     /// state returned by [`Cell::sample_lazy`].
     pub fn accum_lazy<S, F>(&self, init_state: Lazy<S>, f: F) -> Cell<S>
@@ -390,6 +800,62 @@ impl<A: Clone + Send + 'static> Stream<A> {
         }
     }
 
+    /// Accumulate the events of this stream with their [`Monoid`],
+    /// starting from [`Monoid::empty`].
+    pub fn fold_monoid(&self) -> Cell<A>
+    where
+        A: Monoid,
+    {
+        self.accum_lazy(Lazy::new(A::empty), |a: &A, s: &A| s.combine(a))
+    }
+
+    /// Count the events of this stream.
+    pub fn count(&self) -> Cell<usize> {
+        self.map_to(1_usize).fold_monoid()
+    }
+
+    /// Add up the events of this stream, starting from zero.
+    ///
+    /// This is [`fold_monoid`][Stream::fold_monoid] under a name that
+    /// reads better for numbers.
+    pub fn sum(&self) -> Cell<A>
+    where
+        A: Monoid,
+    {
+        self.fold_monoid()
+    }
+
+    /// Track the least event of this stream, or `None` before the
+    /// first event.
+    pub fn min(&self) -> Cell<Option<A>>
+    where
+        A: PartialOrd,
+    {
+        self.map(|a: &A| Some(Min(a.clone())))
+            .fold_monoid()
+            .map(|min_op: &Option<Min<A>>| min_op.clone().map(|Min(a)| a))
+    }
+
+    /// Track the greatest event of this stream, or `None` before the
+    /// first event.
+    pub fn max(&self) -> Cell<Option<A>>
+    where
+        A: PartialOrd,
+    {
+        self.map(|a: &A| Some(Max(a.clone())))
+            .fold_monoid()
+            .map(|max_op: &Option<Max<A>>| max_op.clone().map(|Max(a)| a))
+    }
+
+    /// A variant of [`merge`][Stream::merge] that combines simultaneous
+    /// events with their [`Monoid`], the event from `self` on the left.
+    pub fn merge_monoid(&self, s2: &Stream<A>) -> Stream<A>
+    where
+        A: Monoid,
+    {
+        self.merge(s2, |lhs: &A, rhs: &A| lhs.combine(rhs))
+    }
+
     /// A variant of [`listen`][Stream::listen] that will deregister
     /// the listener automatically if the listener is
     /// garbage-collected.
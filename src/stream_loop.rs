@@ -1,3 +1,4 @@
+use crate::impl_::dep::{Dep, Trace};
 use crate::impl_::stream_loop::StreamLoop as StreamLoopImpl;
 use crate::SodiumCtx;
 use crate::Stream;
@@ -7,6 +8,12 @@ pub struct StreamLoop<A> {
     pub impl_: StreamLoopImpl<A>,
 }
 
+impl<A: Send + Clone + 'static> Trace for StreamLoop<A> {
+    fn trace(&self, deps: &mut Vec<Dep>) {
+        deps.push(self.impl_.stream().to_dep());
+    }
+}
+
 impl<A: Send + Clone + 'static> StreamLoop<A> {
     /// Create a new `StreamLoop` in the given context.
     pub fn new(sodium_ctx: &SodiumCtx) -> StreamLoop<A> {
@@ -1,4 +1,6 @@
+use crate::impl_::dep::{Dep, Trace};
 use crate::impl_::stream_sink::StreamSink as StreamSinkImpl;
+use crate::impl_::stream_sink::WeakStreamSink as WeakStreamSinkImpl;
 use crate::sodium_ctx::SodiumCtx;
 use crate::stream::Stream;
 
@@ -11,6 +13,15 @@ pub struct StreamSink<A> {
     pub impl_: StreamSinkImpl<A>,
 }
 
+/// A weak reference to a [`StreamSink`] that does not keep the sink
+/// alive.
+///
+/// This allows I/O callbacks to stop sending once the FRP graph they
+/// feed has been dropped.
+pub struct WeakStreamSink<A> {
+    pub impl_: WeakStreamSinkImpl<A>,
+}
+
 impl<A> Clone for StreamSink<A> {
     fn clone(&self) -> Self {
         StreamSink {
@@ -19,6 +30,28 @@ impl<A> Clone for StreamSink<A> {
     }
 }
 
+impl<A> Clone for WeakStreamSink<A> {
+    fn clone(&self) -> Self {
+        WeakStreamSink {
+            impl_: self.impl_.clone(),
+        }
+    }
+}
+
+impl<A: Send + 'static> Trace for StreamSink<A> {
+    fn trace(&self, deps: &mut Vec<Dep>) {
+        deps.push(self.impl_.stream().to_dep());
+    }
+}
+
+impl<A> WeakStreamSink<A> {
+    /// Attempt to get a strong reference to the [`StreamSink`],
+    /// returning `None` if it has already been garbage-collected.
+    pub fn upgrade(&self) -> Option<StreamSink<A>> {
+        self.impl_.upgrade().map(|impl_| StreamSink { impl_ })
+    }
+}
+
 impl<A: Clone + Send + 'static> StreamSink<A> {
     /// Create a `StreamSink` that allows calling `send` on it once
     /// per transaction.
@@ -66,4 +99,11 @@ impl<A: Clone + Send + 'static> StreamSink<A> {
     pub fn send(&self, a: A) {
         self.impl_.send(a);
     }
+
+    /// Create a [`WeakStreamSink`] reference to this `StreamSink`.
+    pub fn downgrade(&self) -> WeakStreamSink<A> {
+        WeakStreamSink {
+            impl_: StreamSinkImpl::downgrade(&self.impl_),
+        }
+    }
 }
@@ -1,5 +1,10 @@
-use crate::{lambda1, Cell, CellLoop, Operational, SodiumCtx, Stream, StreamLoop, StreamSink};
+use crate::{
+    lambda, lambda1, Cell, CellLoop, CellMap, CellVec, Dep, DependencyValidation, HistoryChange,
+    IsLambda1, ListDiff, Listener, MapDiff, Max, Min, Monoid, Operational, OrderedRouter, Router,
+    RouterChange, SodiumCtx, Stream, StreamLoop, StreamSink, These, Trace,
+};
 
+use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex};
 
 mod mem_test;
@@ -292,6 +297,141 @@ fn once() {
     assert_memory_freed(sodium_ctx);
 }
 
+fn dependent_count<A>(s: &Stream<A>) -> usize {
+    s.impl_.node.data.dependents.read().unwrap().len()
+}
+
+#[test]
+fn take() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = s
+                .stream()
+                .take(2)
+                .listen(move |a: &&'static str| out.lock().as_mut().unwrap().push(*a));
+        }
+        assert_eq!(1, dependent_count(&s.stream()));
+        s.send("A");
+        s.send("B");
+        assert_eq!(0, dependent_count(&s.stream()));
+        s.send("C");
+        let l2 = s.stream().take(0).listen(|_: &&'static str| panic!());
+        s.send("D");
+        l.unlisten();
+        l2.unlisten();
+        {
+            let lock = out.lock();
+            let out: &Vec<&'static str> = lock.as_ref().unwrap();
+            assert_eq!(vec!["A", "B"], *out);
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn skip() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = s
+                .stream()
+                .skip(2)
+                .listen(move |a: &&'static str| out.lock().as_mut().unwrap().push(*a));
+        }
+        s.send("A");
+        s.send("B");
+        s.send("C");
+        s.send("D");
+        l.unlisten();
+        {
+            let lock = out.lock();
+            let out: &Vec<&'static str> = lock.as_ref().unwrap();
+            assert_eq!(vec!["C", "D"], *out);
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn take_while_skip_while() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let taken = Arc::new(Mutex::new(Vec::new()));
+        let skipped = Arc::new(Mutex::new(Vec::new()));
+        let l1;
+        let l2;
+        {
+            let taken = taken.clone();
+            let skipped = skipped.clone();
+            l1 = s
+                .stream()
+                .take_while(|a: &i32| *a < 3)
+                .listen(move |a: &i32| taken.lock().as_mut().unwrap().push(*a));
+            l2 = s
+                .stream()
+                .skip_while(|a: &i32| *a < 3)
+                .listen(move |a: &i32| skipped.lock().as_mut().unwrap().push(*a));
+        }
+        assert_eq!(2, dependent_count(&s.stream()));
+        for a in &[1, 2, 3, 1, 4] {
+            s.send(*a);
+        }
+        assert_eq!(1, dependent_count(&s.stream()));
+        l1.unlisten();
+        l2.unlisten();
+        assert_eq!(vec![1, 2], *taken.lock().unwrap());
+        assert_eq!(vec![3, 1, 4], *skipped.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn take_until() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let stop: StreamSink<()> = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = s
+                .stream()
+                .take_until(&stop.stream())
+                .listen(move |a: &&'static str| out.lock().as_mut().unwrap().push(*a));
+        }
+        s.send("A");
+        sodium_ctx.transaction(|| {
+            s.send("B");
+            stop.send(());
+        });
+        assert_eq!(0, dependent_count(&s.stream()));
+        assert_eq!(0, dependent_count(&stop.stream()));
+        s.send("C");
+        l.unlisten();
+        {
+            let lock = out.lock();
+            let out: &Vec<&'static str> = lock.as_ref().unwrap();
+            assert_eq!(vec!["A"], *out);
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
 #[test]
 fn hold() {
     let mut sodium_ctx = SodiumCtx::new();
@@ -1544,3 +1684,2063 @@ fn primes2() {
     }
     assert_memory_freed(sodium_ctx);
 }
+
+#[test]
+fn weak_stream_sink() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let ss = sodium_ctx.new_stream_sink::<i32>();
+        let weak_ss = ss.downgrade();
+        let weak_s = ss.stream().downgrade();
+        let l;
+        {
+            let out = out.clone();
+            l = weak_s
+                .upgrade()
+                .unwrap()
+                .listen_weak(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+        }
+        weak_ss.upgrade().unwrap().send(1);
+        ss.send(2);
+        l.unlisten();
+        drop(l);
+        drop(ss);
+        sodium_ctx.impl_.collect_cycles();
+        assert!(weak_ss.upgrade().is_none());
+        assert!(weak_s.upgrade().is_none());
+        {
+            let lock = out.lock();
+            let out: &Vec<i32> = lock.as_ref().unwrap();
+            assert_eq!(vec![1, 2], *out);
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn weak_cell_sink() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let cs = sodium_ctx.new_cell_sink(1);
+        let weak_cs = cs.downgrade();
+        let c = cs.cell().map(|a: &i32| *a * 10);
+        let weak_c = c.downgrade();
+        weak_cs.upgrade().unwrap().send(2);
+        assert_eq!(weak_c.upgrade().unwrap().sample(), 20);
+        drop(c);
+        drop(cs);
+        sodium_ctx.impl_.collect_cycles();
+        assert!(weak_cs.upgrade().is_none());
+        assert!(weak_c.upgrade().is_none());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn trace_user_type() {
+    #[derive(Clone)]
+    struct Prices {
+        bid: Cell<i32>,
+        ask: Cell<i32>,
+        extra: Vec<Cell<i32>>,
+    }
+    impl Trace for Prices {
+        fn trace(&self, deps: &mut Vec<Dep>) {
+            self.bid.trace(deps);
+            self.ask.trace(deps);
+            self.extra.trace(deps);
+        }
+    }
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let bid = sodium_ctx.new_cell_sink(1);
+        let ask = sodium_ctx.new_cell_sink(2);
+        let prices = Prices {
+            bid: bid.cell(),
+            ask: ask.cell(),
+            extra: vec![sodium_ctx.new_cell(10)],
+        };
+        assert_eq!(prices.to_deps().len(), 3);
+        let s = sodium_ctx.new_stream_sink::<i32>();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            let prices2 = prices.clone();
+            l = s
+                .stream()
+                .map(lambda1(
+                    move |a: &i32| {
+                        a + prices2.bid.sample() + prices2.ask.sample() + prices2.extra[0].sample()
+                    },
+                    prices,
+                ))
+                .listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+        }
+        s.send(100);
+        bid.send(5);
+        s.send(200);
+        l.unlisten();
+        {
+            let lock = out.lock();
+            let out: &Vec<i32> = lock.as_ref().unwrap();
+            assert_eq!(vec![113, 217], *out);
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn lambda_macro() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink::<i32>();
+        let ca = sodium_ctx.new_cell_sink(1);
+        let cb = sodium_ctx.new_cell_sink(10);
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            let ca = ca.cell();
+            let cb = cb.cell();
+            let f = lambda!([ca] move |x: &i32| x + ca.sample());
+            assert_eq!(f.deps_op().map(|deps| deps.len()), Some(1));
+            l = s
+                .stream()
+                .map(f)
+                .snapshot(
+                    &ca,
+                    lambda!([cb] |x: &i32, a: &i32| -> i32 { x * a + cb.sample() }),
+                )
+                .listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+        }
+        s.send(1);
+        ca.send(2);
+        cb.send(20);
+        s.send(1);
+        l.unlisten();
+        {
+            let lock = out.lock();
+            let out: &Vec<i32> = lock.as_ref().unwrap();
+            assert_eq!(vec![12, 26], *out);
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+#[should_panic(expected = "undeclared dependency")]
+fn dependency_validation_undeclared() {
+    let sodium_ctx = SodiumCtx::new();
+    sodium_ctx.set_dependency_validation(DependencyValidation::Panic);
+    let s = sodium_ctx.new_stream_sink::<i32>();
+    let c = sodium_ctx.new_cell_sink(1).cell();
+    let _l = s
+        .stream()
+        .map(move |a: &i32| a + c.sample())
+        .listen(|_: &i32| {});
+    s.send(1);
+}
+
+#[test]
+fn dependency_validation_declared() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    sodium_ctx.set_dependency_validation(DependencyValidation::Panic);
+    {
+        let s = sodium_ctx.new_stream_sink::<i32>();
+        let c = sodium_ctx.new_cell_sink(1);
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            let c = c.cell();
+            l = s
+                .stream()
+                .map(lambda!([c] move |a: &i32| a + c.sample()))
+                .gate(&c.map(|a: &i32| *a < 10))
+                .listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+        }
+        s.send(1);
+        c.send(20);
+        s.send(2);
+        c.send(5);
+        s.send(3);
+        l.unlisten();
+        {
+            let lock = out.lock();
+            let out: &Vec<i32> = lock.as_ref().unwrap();
+            assert_eq!(vec![2, 8], *out);
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn dependency_validation_new_cell() {
+    let sodium_ctx = SodiumCtx::new();
+    sodium_ctx.set_dependency_validation(DependencyValidation::Panic);
+    let s = sodium_ctx.new_stream_sink::<i32>();
+    let out = Arc::new(Mutex::new(Vec::new()));
+    let l;
+    {
+        let out = out.clone();
+        let sodium_ctx = sodium_ctx.clone();
+        l = s
+            .stream()
+            .map(move |a: &i32| sodium_ctx.new_cell(*a * 2).sample())
+            .listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+    }
+    s.send(1);
+    s.send(2);
+    l.unlisten();
+    let lock = out.lock();
+    let out: &Vec<i32> = lock.as_ref().unwrap();
+    assert_eq!(vec![2, 4], *out);
+}
+
+#[test]
+fn dependency_validation_listener() {
+    let sodium_ctx = SodiumCtx::new();
+    sodium_ctx.set_dependency_validation(DependencyValidation::Panic);
+    let s = sodium_ctx.new_stream_sink::<i32>();
+    let c = sodium_ctx.new_cell_sink(1).cell();
+    let out = Arc::new(Mutex::new(Vec::new()));
+    let l;
+    {
+        let out = out.clone();
+        l = s
+            .stream()
+            .listen(move |a: &i32| out.lock().as_mut().unwrap().push(a + c.sample()));
+    }
+    s.send(1);
+    l.unlisten();
+    assert_eq!(vec![2], *out.lock().unwrap());
+}
+
+#[test]
+fn dependency_validation_update_panics() {
+    let sodium_ctx = SodiumCtx::new();
+    sodium_ctx.set_dependency_validation(DependencyValidation::Panic);
+    let s = sodium_ctx.new_stream_sink::<i32>();
+    let _l = s
+        .stream()
+        .map(|_: &i32| -> i32 { panic!("update failed") })
+        .listen(|_: &i32| {});
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| s.send(1)));
+    assert!(result.is_err());
+    let updating_nodes = sodium_ctx
+        .impl_
+        .with_data(|data: &mut crate::impl_::sodium_ctx::SodiumCtxData| data.updating_nodes.len());
+    assert_eq!(0, updating_nodes);
+}
+
+#[test]
+fn lift_tuple() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    let l;
+    {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let a = sodium_ctx.new_cell_sink(1);
+        let b = sodium_ctx.new_cell_sink(10);
+        let cells: Vec<Cell<i32>> = (1..=6).map(|x| sodium_ctx.new_cell(x)).collect();
+        let c = Cell::lift_tuple(
+            (
+                &a.cell(),
+                &b.cell(),
+                &cells[0],
+                &cells[1],
+                &cells[2],
+                &cells[3],
+                &cells[4],
+                &cells[5],
+            ),
+            |(a, b, c, d, e, f, g, h): &(i32, i32, i32, i32, i32, i32, i32, i32)| {
+                a + b + c + d + e + f + g + h
+            },
+        );
+        {
+            let out = out.clone();
+            l = c.listen(move |x: &i32| out.lock().as_mut().unwrap().push(*x));
+        }
+        a.send(2);
+        b.send(20);
+        sodium_ctx.transaction(|| {
+            a.send(3);
+            b.send(30);
+        });
+        {
+            let l = out.lock();
+            let out: &Vec<i32> = l.as_ref().unwrap();
+            assert_eq!(vec![32, 33, 43, 54], *out);
+        }
+    }
+    l.unlisten();
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn lift_tuple_glitch() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    let l;
+    {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let calls = Arc::new(Mutex::new(0));
+        let a = sodium_ctx.new_cell_sink(1);
+        let ac = a.cell();
+        let a3 = ac.map(|x: &i32| x * 3);
+        let a5 = ac.map(|x: &i32| x * 5);
+        let c;
+        {
+            let calls = calls.clone();
+            c = Cell::lift_tuple((&ac, &a3, &a5), move |(x, y, z): &(i32, i32, i32)| {
+                **calls.lock().as_mut().unwrap() += 1;
+                format!("{} {} {}", x, y, z)
+            });
+        }
+        {
+            let out = out.clone();
+            l = c.listen(move |a: &String| out.lock().as_mut().unwrap().push(a.clone()));
+        }
+        a.send(2);
+        {
+            let l = out.lock();
+            let out: &Vec<String> = l.as_ref().unwrap();
+            assert_eq!(vec!["1 3 5", "2 6 10"], *out);
+        }
+        assert_eq!(**calls.lock().as_ref().unwrap(), 2);
+    }
+    l.unlisten();
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn snapshot_tuple() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink::<i32>();
+        let b = sodium_ctx.new_cell_sink(0);
+        let cells: Vec<Cell<i32>> = (1..=6).map(|x| sodium_ctx.new_cell(x)).collect();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = s
+                .stream()
+                .snapshot_tuple(
+                    (
+                        &b.cell(),
+                        &cells[0],
+                        &cells[1],
+                        &cells[2],
+                        &cells[3],
+                        &cells[4],
+                        &cells[5],
+                    ),
+                    |x: &i32, (b, c, d, e, f, g, h): &(i32, i32, i32, i32, i32, i32, i32)| {
+                        x + b + c + d + e + f + g + h
+                    },
+                )
+                .listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+        }
+        s.send(100);
+        b.send(10);
+        s.send(200);
+        l.unlisten();
+        {
+            let l = out.lock();
+            let out: &Vec<i32> = l.as_ref().unwrap();
+            assert_eq!(vec![121, 231], *out);
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn sequence() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    let l;
+    {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let sinks: Vec<_> = (0..3).map(|x| sodium_ctx.new_cell_sink(x)).collect();
+        let cells: Vec<Cell<i32>> = sinks.iter().map(|cs| cs.cell()).collect();
+        let c = Cell::sequence(sodium_ctx, &cells);
+        {
+            let out = out.clone();
+            l = c.listen(move |xs: &Vec<i32>| out.lock().as_mut().unwrap().push(xs.clone()));
+        }
+        sinks[1].send(10);
+        sodium_ctx.transaction(|| {
+            sinks[0].send(20);
+            sinks[2].send(30);
+        });
+        let empty: Cell<Vec<i32>> = Cell::sequence(sodium_ctx, &[]);
+        assert_eq!(empty.sample(), Vec::<i32>::new());
+        {
+            let l = out.lock();
+            let out: &Vec<Vec<i32>> = l.as_ref().unwrap();
+            assert_eq!(vec![vec![0, 1, 2], vec![0, 10, 2], vec![20, 10, 30]], *out);
+        }
+    }
+    l.unlisten();
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn switch_sequence() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    let l;
+    {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let a = sodium_ctx.new_cell_sink(1);
+        let b = sodium_ctx.new_cell_sink(2);
+        let members = sodium_ctx.new_cell_sink(vec![a.cell()]);
+        let c = Cell::switch_sequence(&members.cell());
+        {
+            let out = out.clone();
+            l = c.listen(move |xs: &Vec<i32>| out.lock().as_mut().unwrap().push(xs.clone()));
+        }
+        a.send(10);
+        b.send(20);
+        members.send(vec![a.cell(), b.cell()]);
+        b.send(30);
+        members.send(vec![b.cell()]);
+        a.send(40);
+        {
+            let l = out.lock();
+            let out: &Vec<Vec<i32>> = l.as_ref().unwrap();
+            assert_eq!(
+                vec![vec![1], vec![10], vec![10, 20], vec![10, 30], vec![30]],
+                *out
+            );
+        }
+    }
+    l.unlisten();
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn switch_sequence_membership() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    let l;
+    {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let a = sodium_ctx.new_cell_sink(1);
+        let b = sodium_ctx.new_cell_sink(2);
+        let c = sodium_ctx.new_cell_sink(3);
+        let members = sodium_ctx.new_cell_sink(vec![a.cell(), b.cell()]);
+        let seq = Cell::switch_sequence(&members.cell());
+        {
+            let out = out.clone();
+            l = seq.listen(move |xs: &Vec<i32>| out.lock().as_mut().unwrap().push(xs.clone()));
+        }
+        // Reordering keeps the existing inputs attached.
+        sodium_ctx.impl_.collect_cycles();
+        let node_count = sodium_ctx.impl_.node_count();
+        members.send(vec![b.cell(), a.cell()]);
+        sodium_ctx.impl_.collect_cycles();
+        assert_eq!(node_count, sodium_ctx.impl_.node_count());
+        // A retained cell and a new one fire with the membership change.
+        sodium_ctx.transaction(|| {
+            members.send(vec![a.cell(), c.cell(), a.cell()]);
+            a.send(10);
+            c.send(30);
+        });
+        a.send(11);
+        b.send(20);
+        {
+            let l = out.lock();
+            let out: &Vec<Vec<i32>> = l.as_ref().unwrap();
+            assert_eq!(
+                vec![vec![1, 2], vec![2, 1], vec![10, 30, 10], vec![11, 30, 11]],
+                *out
+            );
+        }
+    }
+    l.unlisten();
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn merge_all() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    let l;
+    {
+        let out = Arc::new(Mutex::new(Vec::<String>::new()));
+        let sinks: Vec<StreamSink<String>> = (0..3).map(|_| sodium_ctx.new_stream_sink()).collect();
+        let streams: Vec<Stream<String>> = sinks.iter().map(StreamSink::stream).collect();
+        let s = Stream::merge_all(sodium_ctx, &streams, |l: &String, r: &String| {
+            format!("({} {})", l, r)
+        });
+        let empty = Stream::merge_all(
+            sodium_ctx,
+            Vec::<Stream<String>>::new(),
+            |l: &String, _: &String| l.clone(),
+        );
+        {
+            let out = out.clone();
+            l = Stream::merge_all(sodium_ctx, &[s, empty], |l: &String, _: &String| l.clone())
+                .listen(move |a: &String| out.lock().as_mut().unwrap().push(a.clone()));
+        }
+        sinks[1].send("b".to_string());
+        sodium_ctx.transaction(|| {
+            sinks[2].send("c".to_string());
+            sinks[0].send("a".to_string());
+        });
+        sodium_ctx.transaction(|| {
+            sinks[2].send("c".to_string());
+            sinks[1].send("b".to_string());
+            sinks[0].send("a".to_string());
+        });
+        {
+            let l = out.lock();
+            let out: &Vec<String> = l.as_ref().unwrap();
+            assert_eq!(vec!["b", "(a c)", "((a b) c)"], *out);
+        }
+    }
+    l.unlisten();
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn or_else_all() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    let l;
+    {
+        let out = Arc::new(Mutex::new(Vec::<i32>::new()));
+        let sinks: Vec<StreamSink<i32>> = (0..3).map(|_| sodium_ctx.new_stream_sink()).collect();
+        let s = Stream::or_else_all(sodium_ctx, sinks.iter().map(StreamSink::stream));
+        {
+            let out = out.clone();
+            l = s.listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+        }
+        sinks[2].send(3);
+        sodium_ctx.transaction(|| {
+            sinks[2].send(30);
+            sinks[1].send(20);
+        });
+        {
+            let l = out.lock();
+            let out: &Vec<i32> = l.as_ref().unwrap();
+            assert_eq!(vec![3, 20], *out);
+        }
+    }
+    l.unlisten();
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn merge_with() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    let l;
+    {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let commands: StreamSink<&'static str> = sodium_ctx.new_stream_sink();
+        let acks: StreamSink<u32> = sodium_ctx.new_stream_sink();
+        let s = commands.stream().merge_with(&acks.stream());
+        {
+            let out = out.clone();
+            l = s.listen(move |a: &These<&'static str, u32>| out.lock().as_mut().unwrap().push(*a));
+        }
+        commands.send("start");
+        acks.send(1);
+        sodium_ctx.transaction(|| {
+            acks.send(2);
+            commands.send("stop");
+        });
+        {
+            let l = out.lock();
+            let out: &Vec<These<&'static str, u32>> = l.as_ref().unwrap();
+            assert_eq!(
+                vec![These::This("start"), These::That(1), These::Both("stop", 2)],
+                *out
+            );
+        }
+    }
+    l.unlisten();
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn filter_map() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = s
+                .stream()
+                .filter_map(|a: &&'static str| a.parse::<i32>().ok())
+                .listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+        }
+        s.send("1");
+        s.send("x");
+        s.send("3");
+        l.unlisten();
+        assert_eq!(vec![1, 3], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn partition() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let calls = Arc::new(Mutex::new(0));
+        let evens = Arc::new(Mutex::new(Vec::new()));
+        let odds = Arc::new(Mutex::new(Vec::new()));
+        let l1;
+        let l2;
+        {
+            let calls = calls.clone();
+            let (s_even, s_odd) = s.stream().partition(move |a: &i32| {
+                **calls.lock().as_mut().unwrap() += 1;
+                a % 2 == 0
+            });
+            let evens = evens.clone();
+            let odds = odds.clone();
+            l1 = s_even.listen(move |a: &i32| evens.lock().as_mut().unwrap().push(*a));
+            l2 = s_odd.listen(move |a: &i32| odds.lock().as_mut().unwrap().push(*a));
+        }
+        for a in 1..=5 {
+            s.send(a);
+        }
+        l1.unlisten();
+        l2.unlisten();
+        assert_eq!(vec![2, 4], *evens.lock().unwrap());
+        assert_eq!(vec![1, 3, 5], *odds.lock().unwrap());
+        assert_eq!(5, *calls.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn partition_one_side_dropped() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let evens = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let (s_even, _) = s.stream().partition(|a: &i32| a % 2 == 0);
+            let evens = evens.clone();
+            l = s_even.listen(move |a: &i32| evens.lock().as_mut().unwrap().push(*a));
+        }
+        sodium_ctx.impl_.collect_cycles();
+        for a in 1..=4 {
+            s.send(a);
+        }
+        l.unlisten();
+        assert_eq!(vec![2, 4], *evens.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn calm() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let c = sodium_ctx.new_cell_sink(1);
+        let parity = c.cell().map(|a: &i32| a % 2).calm();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = parity
+                .updates()
+                .listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+        }
+        for a in &[3, 5, 6, 8, 7] {
+            c.send(*a);
+        }
+        assert_eq!(1, parity.sample());
+        l.unlisten();
+        assert_eq!(vec![0, 1], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn calm_by() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let c = sodium_ctx.new_cell_sink(1.0_f64);
+        let calmed = c.cell().calm_by(|a: &f64, b: &f64| (a - b).abs() < 0.5);
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = calmed
+                .updates()
+                .listen(move |a: &f64| out.lock().as_mut().unwrap().push(*a));
+        }
+        for a in &[1.2, 2.0, 2.1, 1.0] {
+            c.send(*a);
+        }
+        l.unlisten();
+        assert_eq!(vec![2.0, 1.0], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn dedup() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let by_key = Arc::new(Mutex::new(Vec::new()));
+        let l1;
+        let l2;
+        {
+            let out = out.clone();
+            let by_key = by_key.clone();
+            l1 = s
+                .stream()
+                .dedup()
+                .listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+            l2 = s
+                .stream()
+                .dedup_by_key(|a: &i32| a / 10)
+                .listen(move |a: &i32| by_key.lock().as_mut().unwrap().push(*a));
+        }
+        for a in &[1, 1, 2, 2, 2, 12, 1] {
+            s.send(*a);
+        }
+        l1.unlisten();
+        l2.unlisten();
+        assert_eq!(vec![1, 2, 12, 1], *out.lock().unwrap());
+        assert_eq!(vec![1, 12, 1], *by_key.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn changes() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let c = sodium_ctx.new_cell_sink(1);
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = c
+                .cell()
+                .changes()
+                .listen(move |a: &(i32, i32)| out.lock().as_mut().unwrap().push(*a));
+        }
+        c.send(2);
+        sodium_ctx.transaction(|| {
+            c.send(3);
+            c.send(4);
+        });
+        l.unlisten();
+        assert_eq!(vec![(1, 2), (2, 4)], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn pairwise() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = s
+                .stream()
+                .pairwise()
+                .listen(move |a: &(char, char)| out.lock().as_mut().unwrap().push(*a));
+        }
+        s.send('a');
+        s.send('b');
+        s.send('c');
+        l.unlisten();
+        assert_eq!(vec![('a', 'b'), ('b', 'c')], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn history() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let c = sodium_ctx.new_cell_sink(1);
+        let history = c.cell().history(3);
+        assert_eq!(VecDeque::from(vec![1]), history.sample());
+        c.send(2);
+        assert_eq!(VecDeque::from(vec![1, 2]), history.sample());
+        c.send(3);
+        c.send(4);
+        assert_eq!(VecDeque::from(vec![2, 3, 4]), history.sample());
+        assert!(c.cell().history(0).sample().is_empty());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn chunks_and_sliding() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let chunks = Arc::new(Mutex::new(Vec::new()));
+        let windows = Arc::new(Mutex::new(Vec::new()));
+        let l1;
+        let l2;
+        {
+            let chunks = chunks.clone();
+            let windows = windows.clone();
+            l1 = s
+                .stream()
+                .chunks(2)
+                .listen(move |a: &Vec<i32>| chunks.lock().as_mut().unwrap().push(a.clone()));
+            l2 = s
+                .stream()
+                .sliding(3)
+                .listen(move |a: &Vec<i32>| windows.lock().as_mut().unwrap().push(a.clone()));
+        }
+        for a in 1..=5 {
+            s.send(a);
+        }
+        l1.unlisten();
+        l2.unlisten();
+        assert_eq!(vec![vec![1, 2], vec![3, 4]], *chunks.lock().unwrap());
+        assert_eq!(
+            vec![vec![1, 2, 3], vec![2, 3, 4], vec![3, 4, 5]],
+            *windows.lock().unwrap()
+        );
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn buffer_while() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let paused = sodium_ctx.new_cell_sink(false);
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = s
+                .stream()
+                .buffer_while(&paused.cell())
+                .listen(move |a: &Vec<i32>| out.lock().as_mut().unwrap().push(a.clone()));
+        }
+        s.send(1);
+        sodium_ctx.transaction(|| {
+            paused.send(true);
+            s.send(2);
+        });
+        s.send(3);
+        sodium_ctx.transaction(|| {
+            s.send(4);
+            paused.send(false);
+        });
+        s.send(5);
+        paused.send(true);
+        paused.send(false);
+        l.unlisten();
+        assert_eq!(vec![vec![1], vec![2, 3, 4], vec![5]], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn monoid_folds() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let count = s.stream().count();
+        let sum = s.stream().sum();
+        let min = s.stream().min();
+        let max = s.stream().max();
+        let words = s.stream().map(|a: &i32| a.to_string()).fold_monoid();
+        assert_eq!(0, count.sample());
+        assert_eq!(None, min.sample());
+        for a in &[3, 1, 4, 1, 5] {
+            s.send(*a);
+        }
+        assert_eq!(5, count.sample());
+        assert_eq!(14, sum.sample());
+        assert_eq!(Some(1), min.sample());
+        assert_eq!(Some(5), max.sample());
+        assert_eq!("31415", words.sample());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn merge_monoid() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let s1 = sodium_ctx.new_stream_sink();
+        let s2 = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = s1
+                .stream()
+                .merge_monoid(&s2.stream())
+                .listen(move |a: &Vec<i32>| out.lock().as_mut().unwrap().push(a.clone()));
+        }
+        s1.send(vec![1]);
+        sodium_ctx.transaction(|| {
+            s2.send(vec![3]);
+            s1.send(vec![2]);
+        });
+        l.unlisten();
+        assert_eq!(vec![vec![1], vec![2, 3]], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn monoid_max_min() {
+    let a = Some(Max(1.5)).combine(&None).combine(&Some(Max(2.5)));
+    assert_eq!(Some(Max(2.5)), a);
+    let b = <Option<Min<i32>>>::empty()
+        .combine(&Some(Min(3)))
+        .combine(&Some(Min(-2)));
+    assert_eq!(Some(Min(-2)), b);
+}
+
+#[test]
+fn cell_ops() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let a = sodium_ctx.new_cell_sink(6);
+        let b = sodium_ctx.new_cell_sink(2);
+        let (ca, cb) = (a.cell(), b.cell());
+        let sum = &ca + &cb;
+        let difference = &ca - &cb;
+        let product = &ca * &cb;
+        let quotient = ca.clone() / cb.clone();
+        let negated = -&ca;
+        assert_eq!(
+            (8, 4, 12, 3, -6),
+            (
+                sum.sample(),
+                difference.sample(),
+                product.sample(),
+                quotient.sample(),
+                negated.sample()
+            )
+        );
+        sodium_ctx.transaction(|| {
+            a.send(10);
+            b.send(5);
+        });
+        assert_eq!(
+            (15, 5, 50, 2, -10),
+            (
+                sum.sample(),
+                difference.sample(),
+                product.sample(),
+                quotient.sample(),
+                negated.sample()
+            )
+        );
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn cell_bool_ops() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let a = sodium_ctx.new_cell_sink(true);
+        let b = sodium_ctx.new_cell_sink(false);
+        let (ca, cb) = (a.cell(), b.cell());
+        let not_a = !&ca;
+        let and = ca.and(&cb);
+        let or = ca.or(&cb);
+        let all = Cell::all(sodium_ctx, &[ca.clone(), cb.clone()]);
+        let any = Cell::any(sodium_ctx, vec![ca.clone(), cb.clone()]);
+        assert_eq!(
+            (false, false, true, false, true),
+            (
+                not_a.sample(),
+                and.sample(),
+                or.sample(),
+                all.sample(),
+                any.sample()
+            )
+        );
+        b.send(true);
+        assert_eq!(
+            (false, true, true, true, true),
+            (
+                not_a.sample(),
+                and.sample(),
+                or.sample(),
+                all.sample(),
+                any.sample()
+            )
+        );
+        sodium_ctx.transaction(|| {
+            a.send(false);
+            b.send(false);
+        });
+        assert_eq!(
+            (true, false, false, false, false),
+            (
+                not_a.sample(),
+                and.sample(),
+                or.sample(),
+                all.sample(),
+                any.sample()
+            )
+        );
+        let none: Vec<Cell<bool>> = Vec::new();
+        assert!(Cell::all(sodium_ctx, &none).sample());
+        assert!(!Cell::any(sodium_ctx, &none).sample());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn cell_vec() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let diffs: StreamSink<Vec<ListDiff<i32>>> = sodium_ctx.new_stream_sink();
+        let list = CellVec::new(vec![1, 2, 3, 4], &diffs.stream());
+        let calls = Arc::new(Mutex::new(0));
+        let doubled = {
+            let calls = calls.clone();
+            list.map(move |a: &i32| {
+                **calls.lock().as_mut().unwrap() += 1;
+                a * 2
+            })
+        };
+        let evens = list.filter(|a: &i32| a % 2 == 0);
+        let len = list.len();
+        let sum = list.sum();
+        let evens_len = evens.len();
+        // Mirror each derived list by applying its diffs, to check they
+        // agree with recomputing from the source.
+        let mirror = |cv: &CellVec<i32>| {
+            let mirror = Arc::new(Mutex::new(cv.sample()));
+            let l = {
+                let mirror = mirror.clone();
+                cv.diffs().listen(move |diffs: &Vec<ListDiff<i32>>| {
+                    let mut mirror = mirror.lock().unwrap();
+                    for diff in diffs {
+                        diff.apply(&mut mirror);
+                    }
+                })
+            };
+            (mirror, l)
+        };
+        let (doubled_mirror, l1) = mirror(&doubled);
+        let (evens_mirror, l2) = mirror(&evens);
+        *calls.lock().unwrap() = 0;
+        let edits = vec![
+            vec![ListDiff::Insert(0, 10)],
+            vec![ListDiff::Replace(2, 7), ListDiff::Remove(1)],
+            vec![ListDiff::Move { from: 0, to: 3 }],
+            vec![ListDiff::Replace(0, 6), ListDiff::Insert(4, 5)],
+            vec![ListDiff::Move { from: 4, to: 0 }, ListDiff::Remove(2)],
+        ];
+        for edit in edits {
+            diffs.send(edit);
+            let values = list.sample();
+            let expected_evens: Vec<i32> = values.iter().cloned().filter(|a| a % 2 == 0).collect();
+            let expected_doubled: Vec<i32> = values.iter().map(|a| a * 2).collect();
+            assert_eq!(expected_doubled, *doubled_mirror.lock().unwrap());
+            assert_eq!(expected_doubled, doubled.sample());
+            assert_eq!(expected_evens, *evens_mirror.lock().unwrap());
+            assert_eq!(expected_evens, evens.sample());
+            assert_eq!(values.len(), len.sample());
+            assert_eq!(values.iter().sum::<i32>(), sum.sample());
+            assert_eq!(expected_evens.len(), evens_len.sample());
+        }
+        assert_eq!(vec![5, 6, 4, 10], list.sample());
+        // One call for each inserted or replaced element, and none for
+        // sampling.
+        assert_eq!(4, *calls.lock().unwrap());
+        assert_eq!(vec![6, 4, 10], evens.cell().sample());
+        // A longer run of edits, checked against a plain `Vec`.
+        let mut expected = list.sample();
+        let mut seed = 1u32;
+        let mut next = |n: usize| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) as usize % n
+        };
+        for k in 0..200 {
+            let edit = if expected.len() < 2 || k % 4 == 0 {
+                ListDiff::Insert(next(expected.len() + 1), next(100) as i32)
+            } else if k % 4 == 1 {
+                ListDiff::Remove(next(expected.len()))
+            } else if k % 4 == 2 {
+                ListDiff::Replace(next(expected.len()), next(100) as i32)
+            } else {
+                ListDiff::Move {
+                    from: next(expected.len()),
+                    to: next(expected.len()),
+                }
+            };
+            edit.apply(&mut expected);
+            diffs.send(vec![edit]);
+        }
+        let expected_evens: Vec<i32> = expected.iter().cloned().filter(|a| a % 2 == 0).collect();
+        assert_eq!(expected, list.sample());
+        assert_eq!(expected_evens, evens.sample());
+        assert_eq!(expected_evens, *evens_mirror.lock().unwrap());
+        assert_eq!(expected.iter().sum::<i32>(), sum.sample());
+        l1.unlisten();
+        l2.unlisten();
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn cell_map() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let diffs: StreamSink<Vec<MapDiff<&'static str, i32>>> = sodium_ctx.new_stream_sink();
+        let mut initial = HashMap::new();
+        initial.insert("a", 1);
+        let map = CellMap::new(initial, &diffs.stream());
+        let a = map.get(&"a");
+        let b = map.get(&"b");
+        let b_doubled = map.map_values(|v: &i32| v * 2).get(&"b");
+        let len = map.len();
+        let keys = map.keys();
+        let a_updates = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let a_updates = a_updates.clone();
+            l = a
+                .updates()
+                .listen(move |a: &Option<i32>| a_updates.lock().as_mut().unwrap().push(*a));
+        }
+        assert_eq!(
+            (Some(1), None, None, 1),
+            (a.sample(), b.sample(), b_doubled.sample(), len.sample())
+        );
+        diffs.send(vec![MapDiff::Upsert("b", 2)]);
+        assert_eq!(
+            (Some(1), Some(2), Some(4), 2),
+            (a.sample(), b.sample(), b_doubled.sample(), len.sample())
+        );
+        diffs.send(vec![
+            MapDiff::Upsert("b", 3),
+            MapDiff::Upsert("c", 4),
+            MapDiff::Remove("a"),
+        ]);
+        assert_eq!(
+            (None, Some(3), Some(6), 2),
+            (a.sample(), b.sample(), b_doubled.sample(), len.sample())
+        );
+        assert_eq!(vec!["b", "c"], keys.sample());
+        // A key observed only after it was inserted starts from the
+        // current value.
+        assert_eq!(Some(4), map.get(&"c").sample());
+        l.unlisten();
+        // Only the edits to "a" reached its cell.
+        assert_eq!(vec![None], *a_updates.lock().unwrap());
+        let mut expected = HashMap::new();
+        expected.insert("b", 3);
+        expected.insert("c", 4);
+        assert_eq!(expected, map.cell().sample());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn cell_map_get_weak() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let diffs: StreamSink<Vec<MapDiff<i32, i32>>> = sodium_ctx.new_stream_sink();
+        let map = CellMap::new(HashMap::new(), &diffs.stream());
+        let keys = map.keys();
+        sodium_ctx.impl_.collect_cycles();
+        let node_count = sodium_ctx.impl_.node_count();
+        {
+            let cells: Vec<Cell<Option<i32>>> = (0..100).map(|k| map.get(&k)).collect();
+            diffs.send((0..100).map(|k| MapDiff::Upsert(k, k * 10)).collect());
+            assert_eq!(Some(420), cells[42].sample());
+        }
+        // The per-key cells are only held by their observers, so
+        // dropping them frees their nodes.
+        sodium_ctx.impl_.collect_cycles();
+        assert_eq!(node_count, sodium_ctx.impl_.node_count());
+        // Removing most keys compacts the order the keys are listed in.
+        diffs.send((0..90).map(MapDiff::Remove).collect());
+        diffs.send(vec![MapDiff::Upsert(5, 50), MapDiff::Remove(95)]);
+        assert_eq!(vec![90, 91, 92, 93, 94, 96, 97, 98, 99, 5], keys.sample());
+        assert_eq!(Some(50), map.get(&5).sample());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn cell_map_get_with_dependency_validation() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    sodium_ctx.set_dependency_validation(DependencyValidation::Panic);
+    {
+        let diffs: StreamSink<Vec<MapDiff<i32, i32>>> = sodium_ctx.new_stream_sink();
+        let mut initial = HashMap::new();
+        initial.insert(1, 10);
+        let map = CellMap::new(initial, &diffs.stream());
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l = {
+            let out = out.clone();
+            map.get(&1)
+                .listen(move |a: &Option<i32>| out.lock().as_mut().unwrap().push(*a))
+        };
+        diffs.send(vec![MapDiff::Upsert(1, 11)]);
+        diffs.send(vec![MapDiff::Remove(1)]);
+        l.unlisten();
+        assert_eq!(vec![Some(10), Some(11), None], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn cell_map_aggregates() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        // Players, each with a team and a score.
+        let diffs: StreamSink<Vec<MapDiff<&'static str, (&'static str, i32)>>> =
+            sodium_ctx.new_stream_sink();
+        let mut initial = HashMap::new();
+        initial.insert("ann", ("red", 3));
+        initial.insert("bob", ("red", 5));
+        initial.insert("cat", ("blue", 4));
+        let players = CellMap::new(initial, &diffs.stream());
+        let team = |p: &(&'static str, i32)| p.0;
+        let score = |p: &(&'static str, i32)| p.1;
+        let counts = players.count_by(team);
+        let sums = players.sum_by(team, score);
+        let mins = players.min_by(team, score);
+        let maxes = players.max_by(team, score);
+        let groups = players.group_by(team);
+        let sum_diffs = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let sum_diffs = sum_diffs.clone();
+            l = sums
+                .diffs()
+                .listen(move |d: &Vec<MapDiff<&'static str, i32>>| {
+                    sum_diffs.lock().as_mut().unwrap().push(d.clone())
+                });
+        }
+        fn at<V: Clone + Send + Sync + 'static>(
+            m: &CellMap<&'static str, V>,
+            k: &'static str,
+        ) -> Option<V> {
+            m.get(&k).sample()
+        }
+        assert_eq!(
+            (Some(2), Some(8), Some(3), Some(5)),
+            (
+                at(&counts, "red"),
+                at(&sums, "red"),
+                at(&mins, "red"),
+                at(&maxes, "red")
+            )
+        );
+        assert_eq!(
+            (Some(1), Some(4), Some(4), Some(4)),
+            (
+                at(&counts, "blue"),
+                at(&sums, "blue"),
+                at(&mins, "blue"),
+                at(&maxes, "blue")
+            )
+        );
+        // Removing the greatest member of a group falls back to the next.
+        diffs.send(vec![MapDiff::Remove("bob")]);
+        assert_eq!(
+            (Some(1), Some(3), Some(3), Some(3)),
+            (
+                at(&counts, "red"),
+                at(&sums, "red"),
+                at(&mins, "red"),
+                at(&maxes, "red")
+            )
+        );
+        // Moving a player between teams touches both, and emptying a
+        // team removes it.
+        diffs.send(vec![MapDiff::Upsert("cat", ("red", 1))]);
+        assert_eq!(
+            (Some(2), Some(4), Some(1), Some(3)),
+            (
+                at(&counts, "red"),
+                at(&sums, "red"),
+                at(&mins, "red"),
+                at(&maxes, "red")
+            )
+        );
+        assert_eq!(
+            (None, None, None, None),
+            (
+                at(&counts, "blue"),
+                at(&sums, "blue"),
+                at(&mins, "blue"),
+                at(&maxes, "blue")
+            )
+        );
+        let mut red_players = HashMap::new();
+        red_players.insert("ann", ("red", 3));
+        red_players.insert("cat", ("red", 1));
+        assert_eq!(Some(red_players), groups.get(&"red").sample());
+        // A new team only emits an edit for that team.
+        diffs.send(vec![MapDiff::Upsert("dan", ("green", 7))]);
+        assert_eq!(Some(7), sums.get(&"green").sample());
+        l.unlisten();
+        assert_eq!(
+            vec![
+                vec![MapDiff::Upsert("red", 3)],
+                vec![MapDiff::Remove("blue"), MapDiff::Upsert("red", 4)],
+                vec![MapDiff::Upsert("green", 7)],
+            ],
+            *sum_diffs.lock().unwrap()
+        );
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn cell_map_join() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let name_sink: StreamSink<Vec<MapDiff<i32, &'static str>>> = sodium_ctx.new_stream_sink();
+        let age_sink: StreamSink<Vec<MapDiff<i32, u32>>> = sodium_ctx.new_stream_sink();
+        let mut initial_names = HashMap::new();
+        initial_names.insert(1, "ann");
+        initial_names.insert(2, "bob");
+        let mut initial_ages = HashMap::new();
+        initial_ages.insert(1, 30);
+        let names = CellMap::new(initial_names, &name_sink.stream());
+        let ages = CellMap::new(initial_ages, &age_sink.stream());
+        let inner = names.join(&ages);
+        let left = names.left_join(&ages);
+        assert_eq!(Some(("ann", 30)), inner.get(&1).sample());
+        assert_eq!(None, inner.get(&2).sample());
+        assert_eq!(Some(("bob", None)), left.get(&2).sample());
+        let inner_diffs = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let inner_diffs = inner_diffs.clone();
+            l = inner
+                .diffs()
+                .listen(move |d: &Vec<MapDiff<i32, (&'static str, u32)>>| {
+                    inner_diffs.lock().as_mut().unwrap().push(d.clone())
+                });
+        }
+        // Edits to both sides in the same transaction are combined.
+        sodium_ctx.transaction(|| {
+            name_sink.send(vec![MapDiff::Upsert(3, "cat")]);
+            age_sink.send(vec![MapDiff::Upsert(2, 40), MapDiff::Upsert(3, 50)]);
+        });
+        assert_eq!(Some(("bob", 40)), inner.get(&2).sample());
+        assert_eq!(Some(("cat", Some(50))), left.get(&3).sample());
+        age_sink.send(vec![MapDiff::Remove(1)]);
+        assert_eq!(None, inner.get(&1).sample());
+        assert_eq!(Some(("ann", None)), left.get(&1).sample());
+        name_sink.send(vec![MapDiff::Remove(2)]);
+        assert_eq!(None, left.get(&2).sample());
+        // Keys on only one side never enter the inner join, so they
+        // are not reported removed from it either.
+        age_sink.send(vec![MapDiff::Upsert(4, 60)]);
+        name_sink.send(vec![MapDiff::Upsert(5, "dan")]);
+        assert_eq!(None, inner.get(&4).sample());
+        l.unlisten();
+        assert_eq!(
+            vec![
+                vec![
+                    MapDiff::Upsert(3, ("cat", 50)),
+                    MapDiff::Upsert(2, ("bob", 40))
+                ],
+                vec![MapDiff::Remove(1)],
+                vec![MapDiff::Remove(2)],
+            ],
+            *inner_diffs.lock().unwrap()
+        );
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn router_unmatched_and_changes() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let sink: StreamSink<(i32, &'static str)> = sodium_ctx.new_stream_sink();
+        let router = Router::new(sodium_ctx, &sink.stream(), |a: &(i32, &'static str)| {
+            vec![a.0]
+        });
+        let unmatched_out = Arc::new(Mutex::new(Vec::new()));
+        let changes_out = Arc::new(Mutex::new(Vec::new()));
+        let l1;
+        let l2;
+        {
+            let unmatched_out = unmatched_out.clone();
+            l1 = router.unmatched().listen(move |a: &(i32, &'static str)| {
+                unmatched_out.lock().as_mut().unwrap().push(a.1)
+            });
+        }
+        {
+            let changes_out = changes_out.clone();
+            l2 = router.changes().listen(move |c: &RouterChange<i32>| {
+                changes_out.lock().as_mut().unwrap().push(c.clone())
+            });
+        }
+        assert!(router.active_keys().is_empty());
+        sink.send((1, "a"));
+        let one = router.filter_matches(&1);
+        let one_out = Arc::new(Mutex::new(Vec::new()));
+        let l3;
+        {
+            let one_out = one_out.clone();
+            l3 = one
+                .listen(move |a: &(i32, &'static str)| one_out.lock().as_mut().unwrap().push(a.1));
+        }
+        assert_eq!(vec![1], router.active_keys());
+        sink.send((1, "b"));
+        sink.send((2, "c"));
+        l3.unlisten();
+        drop(one);
+        sodium_ctx.impl_.collect_cycles();
+        assert!(router.active_keys().is_empty());
+        sink.send((1, "d"));
+        l1.unlisten();
+        l2.unlisten();
+        assert_eq!(vec!["a", "c", "d"], *unmatched_out.lock().unwrap());
+        assert_eq!(vec!["b"], *one_out.lock().unwrap());
+        assert_eq!(
+            vec![RouterChange::Added(1), RouterChange::Removed(1)],
+            *changes_out.lock().unwrap()
+        );
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn router_changes_balanced() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let sink: StreamSink<i32> = sodium_ctx.new_stream_sink();
+        let router = Router::new(sodium_ctx, &sink.stream(), |a: &i32| vec![*a]);
+        let changes_out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let changes_out = changes_out.clone();
+            l = router.changes().listen(move |c: &RouterChange<i32>| {
+                changes_out.lock().as_mut().unwrap().push(c.clone())
+            });
+        }
+        // Re-subscribe to a key whose stream was dropped. A removal
+        // found by garbage collection outside a transaction is reported
+        // in the next one, ahead of the next addition.
+        let one = sodium_ctx.transaction(|| {
+            drop(router.filter_matches(&1));
+            router.filter_matches(&1)
+        });
+        drop(one);
+        sodium_ctx.impl_.collect_cycles();
+        assert_eq!(vec![RouterChange::Added(1)], *changes_out.lock().unwrap());
+        let one = router.filter_matches(&1);
+        drop(one);
+        sodium_ctx.impl_.collect_cycles();
+        sink.send(1);
+        l.unlisten();
+        assert_eq!(
+            vec![
+                RouterChange::Added(1),
+                RouterChange::Removed(1),
+                RouterChange::Added(1),
+                RouterChange::Removed(1)
+            ],
+            *changes_out.lock().unwrap()
+        );
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn router_new_single() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let sink: StreamSink<i32> = sodium_ctx.new_stream_sink();
+        let router = Router::new_single(sodium_ctx, &sink.stream(), |a: &i32| {
+            if *a >= 0 {
+                Some(a % 2 == 0)
+            } else {
+                None
+            }
+        });
+        let evens = router.filter_matches(&true);
+        let unmatched = router.unmatched();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = evens
+                .or_else(&unmatched.map(|a: &i32| a * 100))
+                .listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+        }
+        for a in &[1, 2, -3, 4] {
+            sink.send(*a);
+        }
+        l.unlisten();
+        assert_eq!(vec![100, 2, -300, 4], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn ordered_router() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let sink: StreamSink<(String, i32)> = sodium_ctx.new_stream_sink();
+        let router = OrderedRouter::new(sodium_ctx, &sink.stream(), |a: &(String, i32)| {
+            Some(a.0.clone())
+        });
+        let eur = router.filter_prefix("EUR");
+        let range = router.filter_range("EURUSD".to_string()..="GBPUSD".to_string());
+        let exact = router.filter_matches(&"GBPUSD".to_string());
+        let all = router.filter_prefix("");
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let mut listeners = Vec::new();
+        for (name, s) in [
+            ("eur", &eur),
+            ("range", &range),
+            ("exact", &exact),
+            ("all", &all),
+        ] {
+            let out = out.clone();
+            listeners.push(
+                s.listen(move |a: &(String, i32)| out.lock().as_mut().unwrap().push((name, a.1))),
+            );
+        }
+        // Subscribing to the same range again shares the stream.
+        let eur2 = router.filter_range("EUR".to_string().."EUS".to_string());
+        assert!(Arc::ptr_eq(&eur.impl_.node.data, &eur2.impl_.node.data));
+        for (i, sym) in ["EUR", "EURJPY", "EURUSD", "EUS", "GBPUSD", "USDJPY"]
+            .iter()
+            .enumerate()
+        {
+            sink.send((sym.to_string(), i as i32));
+        }
+        for l in listeners {
+            l.unlisten();
+        }
+        let mut out = out.lock().unwrap().clone();
+        out.sort();
+        assert_eq!(
+            vec![
+                ("all", 0),
+                ("all", 1),
+                ("all", 2),
+                ("all", 3),
+                ("all", 4),
+                ("all", 5),
+                ("eur", 0),
+                ("eur", 1),
+                ("eur", 2),
+                ("exact", 4),
+                ("range", 2),
+                ("range", 3),
+                ("range", 4),
+            ],
+            out
+        );
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn ordered_router_overlapping_ranges() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let sink: StreamSink<i32> = sodium_ctx.new_stream_sink();
+        let router = OrderedRouter::new(sodium_ctx, &sink.stream(), |a: &i32| Some(*a));
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let mut listeners = Vec::new();
+        let mut subscribe = |name: &'static str, s: Stream<i32>| {
+            let out = out.clone();
+            listeners.push(s.listen(move |a: &i32| out.lock().as_mut().unwrap().push((name, *a))));
+        };
+        subscribe("0..=2", router.filter_range(0..=2));
+        subscribe("1..4", router.filter_range(1..4));
+        subscribe("..1", router.filter_range(..1));
+        subscribe("3..", router.filter_range(3..));
+        subscribe("2..=2", router.filter_range(2..=2));
+        subscribe("=2", router.filter_matches(&2));
+        for a in 0..5 {
+            sink.send(a);
+        }
+        for l in listeners {
+            l.unlisten();
+        }
+        let mut out = out.lock().unwrap().clone();
+        out.sort();
+        assert_eq!(
+            vec![
+                ("..1", 0),
+                ("0..=2", 0),
+                ("0..=2", 1),
+                ("0..=2", 2),
+                ("1..4", 1),
+                ("1..4", 2),
+                ("1..4", 3),
+                ("2..=2", 2),
+                ("3..", 3),
+                ("3..", 4),
+                ("=2", 2),
+            ],
+            out
+        );
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn group_by() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let sink: StreamSink<(&'static str, i32)> = sodium_ctx.new_stream_sink();
+        let groups = sink.stream().group_by(|a: &(&'static str, i32)| a.0);
+        let keys_out = Arc::new(Mutex::new(Vec::new()));
+        let a_out = Arc::new(Mutex::new(Vec::new()));
+        let a_listeners = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let keys_out = keys_out.clone();
+            let a_out = a_out.clone();
+            let a_listeners = a_listeners.clone();
+            l = groups.listen(
+                move |(k, sub): &(&'static str, Stream<(&'static str, i32)>)| {
+                    keys_out.lock().as_mut().unwrap().push(*k);
+                    // Only the substream for "a" is kept; the others are freed.
+                    if *k == "a" {
+                        let a_out = a_out.clone();
+                        let l = sub.listen(move |a: &(&'static str, i32)| {
+                            a_out.lock().as_mut().unwrap().push(a.1)
+                        });
+                        a_listeners.lock().as_mut().unwrap().push(l);
+                    }
+                },
+            );
+        }
+        sink.send(("a", 1));
+        sink.send(("b", 2));
+        sink.send(("a", 3));
+        sodium_ctx.impl_.collect_cycles();
+        sink.send(("b", 4));
+        sink.send(("a", 5));
+        l.unlisten();
+        for l in a_listeners.lock().unwrap().drain(..) {
+            l.unlisten();
+        }
+        // The first event for a key reaches its substream too.
+        assert_eq!(vec![1, 3, 5], *a_out.lock().unwrap());
+        // The substream for "b" was freed, so "b" was seen again.
+        assert_eq!(vec!["a", "b", "b"], *keys_out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn split_each() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let sink: StreamSink<Vec<i32>> = sodium_ctx.new_stream_sink();
+        let s = Operational::split_each(&sink.stream());
+        // Each element fires in a transaction of its own, so it sees the
+        // previous element in the cell.
+        let last = s.hold(0);
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = s
+                .snapshot(&last, |a: &i32, prev: &i32| (*a, *prev))
+                .listen(move |a: &(i32, i32)| out.lock().as_mut().unwrap().push(*a));
+        }
+        sink.send(vec![1, 2, 3]);
+        sink.send(vec![]);
+        sink.send(vec![4]);
+        l.unlisten();
+        assert_eq!(vec![(1, 0), (2, 1), (3, 2), (4, 3)], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn split_each_ordering() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    {
+        let sink: StreamSink<Vec<&'static str>> = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = Operational::split_each(&sink.stream())
+                .listen(move |a: &&'static str| out.lock().as_mut().unwrap().push(*a));
+        }
+        // The elements are posted as the collection propagates at the end
+        // of the transaction, after the callbacks posted within it.
+        sodium_ctx.transaction(|| {
+            {
+                let out = out.clone();
+                sodium_ctx.post(move || out.lock().as_mut().unwrap().push("before"));
+            }
+            sink.send(vec!["a", "b"]);
+            {
+                let out = out.clone();
+                sodium_ctx.post(move || out.lock().as_mut().unwrap().push("after"));
+            }
+        });
+        assert_eq!(vec!["before", "after", "a", "b"], *out.lock().unwrap());
+        out.lock().as_mut().unwrap().clear();
+        // The input fires again from an earlier post callback, before
+        // the first elements have been sent, and from within nested
+        // transactions.
+        sodium_ctx.transaction(|| {
+            {
+                let sink = sink.clone();
+                let sodium_ctx2 = sodium_ctx.clone();
+                sodium_ctx.post(move || {
+                    sodium_ctx2.transaction(|| sink.send(vec!["c", "d"]));
+                });
+            }
+            sodium_ctx.transaction(|| sink.send(vec!["a", "b"]));
+        });
+        l.unlisten();
+        assert_eq!(vec!["a", "b", "c", "d"], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+// A log for a `Recorder` that can be read while it is still in use.
+#[cfg(feature = "recording")]
+struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+#[cfg(feature = "recording")]
+impl std::io::Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "recording")]
+#[test]
+fn record_and_replay() {
+    use crate::{CellSink, Recorder, Replayer};
+
+    // Run the same graph over the given sinks, returning what it output.
+    fn run(
+        s: &StreamSink<String>,
+        c: &CellSink<i32>,
+        drive: impl FnOnce(),
+    ) -> Vec<These<String, i32>> {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = s
+                .stream()
+                .merge_with(&c.cell().updates())
+                .listen(move |a: &These<String, i32>| out.lock().as_mut().unwrap().push(a.clone()));
+        }
+        drive();
+        l.unlisten();
+        let out = out.lock().unwrap().clone();
+        out
+    }
+
+    let buf = Arc::new(Mutex::new(Vec::new()));
+    let recorded = {
+        let sodium_ctx = SodiumCtx::new();
+        let sodium_ctx = &sodium_ctx;
+        let s: StreamSink<String> = sodium_ctx.new_stream_sink();
+        let c = sodium_ctx.new_cell_sink(0);
+        let recorder = Recorder::new(sodium_ctx, SharedBuf(buf.clone()));
+        recorder.record_stream_sink("text", &s, |a: &String| a.clone());
+        recorder.record_cell_sink("count", &c, |a: &i32| a.to_string());
+        let out = run(&s, &c, || {
+            s.send("a line\nand another \\".to_string());
+            c.send(1);
+            sodium_ctx.transaction(|| {
+                s.send("both".to_string());
+                c.send(2);
+            });
+            c.send(3);
+            sodium_ctx.transaction(|| {
+                c.send(4);
+                c.send(5);
+            });
+        });
+        // Values sent once recording has stopped are not logged.
+        recorder.stop();
+        s.send("unrecorded".to_string());
+        c.send(6);
+        recorder.flush().unwrap();
+        out
+    };
+    assert_eq!(
+        vec![
+            These::This("a line\nand another \\".to_string()),
+            These::That(1),
+            These::Both("both".to_string(), 2),
+            These::That(3),
+            These::That(5),
+        ],
+        recorded
+    );
+    let log = buf.lock().unwrap().clone();
+    assert_eq!(7, log.iter().filter(|b| **b == b'\n').count());
+    let replayed = {
+        let sodium_ctx = SodiumCtx::new();
+        let sodium_ctx = &sodium_ctx;
+        let s: StreamSink<String> = sodium_ctx.new_stream_sink();
+        let c = sodium_ctx.new_cell_sink(0);
+        let mut replayer = Replayer::new(sodium_ctx);
+        replayer.replay_stream_sink("text", &s, |a: &str| Some(a.to_string()));
+        replayer.replay_cell_sink("count", &c, |a: &str| a.parse().ok());
+        run(&s, &c, || replayer.replay(&log[..]).unwrap())
+    };
+    assert_eq!(recorded, replayed);
+}
+
+#[cfg(feature = "recording")]
+#[test]
+fn replay_coalesced() {
+    use crate::{Recorder, Replayer};
+
+    // Return what the summing sink fires while `drive` runs.
+    fn run(s: &StreamSink<i32>, drive: impl FnOnce()) -> Vec<i32> {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l = {
+            let out = out.clone();
+            s.stream()
+                .listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a))
+        };
+        drive();
+        l.unlisten();
+        let out = out.lock().unwrap().clone();
+        out
+    }
+
+    let buf = Arc::new(Mutex::new(Vec::new()));
+    let recorded = {
+        let sodium_ctx = SodiumCtx::new();
+        let s = sodium_ctx.new_stream_sink_with_coalescer(|a: &i32, b: &i32| a + b);
+        let recorder = Recorder::new(&sodium_ctx, SharedBuf(buf.clone()));
+        recorder.record_stream_sink("n", &s, |a: &i32| a.to_string());
+        run(&s, || {
+            sodium_ctx.transaction(|| {
+                s.send(1);
+                s.send(2);
+            })
+        })
+    };
+    assert_eq!(vec![3], recorded);
+    let log = buf.lock().unwrap().clone();
+    let replayed = {
+        let sodium_ctx = SodiumCtx::new();
+        let s = sodium_ctx.new_stream_sink_with_coalescer(|a: &i32, b: &i32| a + b);
+        let mut replayer = Replayer::new(&sodium_ctx);
+        replayer.replay_stream_sink("n", &s, |a: &str| a.parse().ok());
+        run(&s, || replayer.replay(&log[..]).unwrap())
+    };
+    assert_eq!(recorded, replayed);
+}
+
+#[test]
+fn snapshot_and_restore_state() {
+    type Graph = (
+        StreamSink<i32>,
+        Cell<i32>,
+        Cell<String>,
+        Arc<Mutex<Vec<i32>>>,
+        Listener,
+    );
+
+    // Build the same graph in a context, returning the sink, the
+    // cells and a listener recording the outputs.
+    fn build(sodium_ctx: &SodiumCtx) -> Graph {
+        let s: StreamSink<i32> = sodium_ctx.new_stream_sink();
+        let total = s.stream().accum_named("total", 0, |a: &i32, s: &i32| a + s);
+        let last = s
+            .stream()
+            .map(|a: &i32| a.to_string())
+            .hold_named("last", String::new());
+        let deltas = s
+            .stream()
+            .collect_named("previous", 0, |a: &i32, s: &i32| (a - s, *a));
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l = {
+            let out = out.clone();
+            deltas.listen(move |a: &i32| out.lock().unwrap().push(*a))
+        };
+        (s, total, last, out, l)
+    }
+
+    let state = {
+        let sodium_ctx = SodiumCtx::new();
+        let (s, total, last, out, l) = build(&sodium_ctx);
+        s.send(3);
+        s.send(5);
+        assert_eq!(8, total.sample());
+        assert_eq!("5", last.sample());
+        assert_eq!(vec![3, 2], *out.lock().unwrap());
+        assert_eq!(
+            vec!["last", "previous", "total"],
+            sodium_ctx.state_store().names()
+        );
+        let state = sodium_ctx.snapshot_state();
+        l.unlisten();
+        state
+    };
+    assert_eq!(Some("8"), state.get("total").map(String::as_str));
+    assert_eq!(Some("5"), state.get("previous").map(String::as_str));
+
+    let sodium_ctx = SodiumCtx::new();
+    let mut restored = state.clone();
+    restored.insert("last".to_string(), "9".to_string());
+    sodium_ctx.restore_state(restored);
+    let (s, total, last, out, l) = build(&sodium_ctx);
+    assert_eq!(8, total.sample());
+    assert_eq!("9", last.sample());
+    s.send(2);
+    assert_eq!(10, total.sample());
+    assert_eq!(vec![-3], *out.lock().unwrap());
+    l.unlisten();
+
+    // Freed accumulators are dropped from the snapshot.
+    drop(total);
+    sodium_ctx.impl_.collect_cycles();
+    let state = sodium_ctx.snapshot_state();
+    assert!(!state.contains_key("total"));
+    assert_eq!(Some("2"), state.get("last").map(String::as_str));
+}
+
+#[test]
+fn transaction_history() {
+    let sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &sodium_ctx;
+    let price = sodium_ctx.new_cell_sink(10);
+    let quantity = sodium_ctx.new_cell_sink(1);
+    let total = price
+        .cell()
+        .lift2(&quantity.cell(), |p: &i32, q: &i32| p * q);
+    price.cell().track_history("price");
+    assert!(sodium_ctx.history().transaction_ids().is_empty());
+
+    sodium_ctx.set_history_capacity(3);
+    total.track_history("cart_total");
+    let tracked = sodium_ctx.transaction_id();
+    price.send(12);
+    let t1 = sodium_ctx.transaction_id();
+    sodium_ctx.transaction(|| {
+        quantity.send(2);
+        price.send(15);
+    });
+    let t2 = sodium_ctx.transaction_id();
+    let history = sodium_ctx.history();
+    assert_eq!(vec![tracked, t1, t2], history.transaction_ids());
+    assert_eq!(
+        Some("10"),
+        history.at(tracked).unwrap().value_of("cart_total")
+    );
+    assert_eq!(Some("12"), history.at(t1).unwrap().value_of("cart_total"));
+    assert_eq!(Some("30"), history.at(t2).unwrap().value_of("cart_total"));
+    assert_eq!(Some("15"), history.latest().unwrap().value_of("price"));
+    assert_eq!(
+        Some(vec![HistoryChange {
+            name: "cart_total".to_string(),
+            before: Some("12".to_string()),
+            after: Some("30".to_string()),
+        }]),
+        history.diff(t1, t2).map(|changes| changes
+            .into_iter()
+            .filter(|change| change.name == "cart_total")
+            .collect::<Vec<_>>())
+    );
+    assert_eq!(
+        vec!["cart_total", "price"],
+        history
+            .diff(t1, t2)
+            .unwrap()
+            .into_iter()
+            .map(|change| change.name)
+            .collect::<Vec<_>>()
+    );
+
+    // Only the last three transactions are kept, including ones that
+    // changed no tracked cell.
+    history.untrack("price");
+    sodium_ctx.transaction(|| {});
+    let t3 = sodium_ctx.transaction_id();
+    assert_eq!(vec![t1, t2, t3], history.transaction_ids());
+    assert!(history.at(tracked).is_none());
+    assert_eq!(None, history.at(t3).unwrap().value_of("price"));
+    assert_eq!(
+        Some(vec![HistoryChange {
+            name: "price".to_string(),
+            before: Some("15".to_string()),
+            after: None,
+        }]),
+        history.diff(t2, t3)
+    );
+}